@@ -0,0 +1,26 @@
+use std::process::Command;
+
+fn main() {
+    // Builds without the repository, e.g. from a source archive,
+    // can provide the revision explicitly.
+    let git_sha = std::env::var("BUDGETERS_GIT_SHA")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+                .map(|sha| sha.trim().to_owned())
+        })
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    let build_time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    println!("cargo:rustc-env=BUDGETERS_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=BUDGETERS_BUILD_TIME={build_time}");
+    println!("cargo:rerun-if-env-changed=BUDGETERS_GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
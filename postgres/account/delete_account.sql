@@ -0,0 +1,2 @@
+DELETE FROM credentials.auth_info
+WHERE username = $1;
@@ -0,0 +1,4 @@
+SELECT username
+FROM credentials.auth_info
+WHERE deleted_at IS NOT NULL AND deleted_at <= $1
+FOR UPDATE;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET deleted_at = $2
+WHERE username = $1 AND deleted_at IS NULL;
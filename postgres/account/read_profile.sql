@@ -0,0 +1,3 @@
+SELECT username, permissions, created_at, disabled, password_reset_required, suspended_until, suspension_reason
+FROM credentials.auth_info
+WHERE username = $1;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET deleted_at = NULL
+WHERE username = $1 AND deleted_at IS NOT NULL;
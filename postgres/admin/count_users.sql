@@ -0,0 +1,4 @@
+SELECT COUNT(*) AS total
+FROM credentials.auth_info
+WHERE $1::VARCHAR IS NULL
+  OR username ILIKE '%' || replace(replace(replace($1, '\', '\\'), '%', '\%'), '_', '\_') || '%' ESCAPE '\';
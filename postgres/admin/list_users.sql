@@ -0,0 +1,7 @@
+-- Wildcards given in the search are matched literally.
+SELECT username, permissions, disabled, password_reset_required, created_at, deleted_at
+FROM credentials.auth_info
+WHERE $1::VARCHAR IS NULL
+  OR username ILIKE '%' || replace(replace(replace($1, '\', '\\'), '%', '\%'), '_', '\_') || '%' ESCAPE '\'
+ORDER BY username
+LIMIT $2 OFFSET $3;
@@ -0,0 +1,4 @@
+SELECT username
+FROM credentials.auth_info
+WHERE username = $1
+FOR SHARE;
@@ -0,0 +1,3 @@
+SELECT username, permissions, disabled, password_reset_required, created_at, deleted_at
+FROM credentials.auth_info
+WHERE username = $1;
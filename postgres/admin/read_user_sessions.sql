@@ -0,0 +1,4 @@
+SELECT expiration_date
+FROM credentials.session_info
+WHERE username = $1
+ORDER BY expiration_date DESC;
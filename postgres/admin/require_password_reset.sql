@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET password_reset_required = TRUE
+WHERE username = $1;
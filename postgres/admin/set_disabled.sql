@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET disabled = $2
+WHERE username = $1;
@@ -0,0 +1,9 @@
+-- Client details of events caused by someone else belong to that someone.
+SELECT event_id, occurred_at, event_type, actor, target,
+  CASE WHEN events.actor_id = auth_info.user_id THEN ip_address END AS ip_address,
+  CASE WHEN events.actor_id = auth_info.user_id THEN user_agent END AS user_agent,
+  details
+FROM audit.events
+JOIN credentials.auth_info ON auth_info.username = $1
+WHERE events.actor_id = auth_info.user_id OR events.target_id = auth_info.user_id
+ORDER BY event_id;
@@ -0,0 +1,7 @@
+SELECT COUNT(*) AS total
+FROM audit.events
+WHERE ($1::VARCHAR IS NULL OR event_type = $1)
+  AND ($2::VARCHAR IS NULL OR actor = $2)
+  AND ($3::VARCHAR IS NULL OR target = $3)
+  AND ($4::TIMESTAMP IS NULL OR occurred_at >= $4)
+  AND ($5::TIMESTAMP IS NULL OR occurred_at < $5);
@@ -0,0 +1,4 @@
+SELECT COUNT(*) AS total
+FROM audit.events
+JOIN credentials.auth_info ON auth_info.username = $1
+WHERE events.actor_id = auth_info.user_id OR events.target_id = auth_info.user_id;
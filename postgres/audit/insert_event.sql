@@ -0,0 +1,7 @@
+INSERT INTO audit.events (event_type, actor, actor_id, target, target_id, ip_address, user_agent, details)
+VALUES (
+  $1,
+  $2::VARCHAR, (SELECT user_id FROM credentials.auth_info WHERE username = $2::VARCHAR),
+  $3::VARCHAR, (SELECT user_id FROM credentials.auth_info WHERE username = $3::VARCHAR),
+  $4, $5, $6
+);
@@ -0,0 +1,9 @@
+SELECT event_id, occurred_at, event_type, actor, target, ip_address, user_agent, details
+FROM audit.events
+WHERE ($1::VARCHAR IS NULL OR event_type = $1)
+  AND ($2::VARCHAR IS NULL OR actor = $2)
+  AND ($3::VARCHAR IS NULL OR target = $3)
+  AND ($4::TIMESTAMP IS NULL OR occurred_at >= $4)
+  AND ($5::TIMESTAMP IS NULL OR occurred_at < $5)
+ORDER BY event_id DESC
+LIMIT $6 OFFSET $7;
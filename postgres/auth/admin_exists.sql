@@ -0,0 +1,5 @@
+SELECT EXISTS (
+  SELECT 1
+  FROM credentials.auth_info
+  WHERE permissions = 'Admin'
+) AS admin_exists;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET salt = $2, password_hash = $3, memory_blocks = $4, iterations = $5, parallelism = $6, password_reset_required = FALSE
+WHERE username = $1;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET permissions = $2
+WHERE username = $1 AND NOT disabled AND deleted_at IS NULL;
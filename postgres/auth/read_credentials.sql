@@ -0,0 +1,3 @@
+SELECT salt, password_hash, memory_blocks, iterations, parallelism, disabled, password_reset_required, suspended_until, suspension_reason
+FROM credentials.auth_info
+WHERE username = $1 AND deleted_at IS NULL;
@@ -1,2 +1,2 @@
-INSERT INTO credentials.auth_info (username, salt, password_hash, permissions)
-VALUES ($1, $2, $3, $4);
+INSERT INTO credentials.auth_info (username, salt, password_hash, permissions, memory_blocks, iterations, parallelism)
+VALUES ($1, $2, $3, $4, $5, $6, $7);
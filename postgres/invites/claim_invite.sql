@@ -0,0 +1,6 @@
+UPDATE credentials.invites
+SET uses = uses + 1
+WHERE code = $1
+  AND uses < max_uses
+  AND (expires_at IS NULL OR expires_at > (NOW() AT TIME ZONE 'utc'))
+RETURNING invite_id, permissions;
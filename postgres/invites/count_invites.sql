@@ -0,0 +1,2 @@
+SELECT COUNT(*) AS total
+FROM credentials.invites;
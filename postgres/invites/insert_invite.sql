@@ -0,0 +1,3 @@
+INSERT INTO credentials.invites (code, created_by, expires_at, max_uses, permissions)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING invite_id;
@@ -0,0 +1,4 @@
+SELECT invite_id, code, created_by, created_at, expires_at, max_uses, uses, permissions
+FROM credentials.invites
+ORDER BY invite_id DESC
+LIMIT $1 OFFSET $2;
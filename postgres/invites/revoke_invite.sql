@@ -0,0 +1,2 @@
+DELETE FROM credentials.invites
+WHERE invite_id = $1;
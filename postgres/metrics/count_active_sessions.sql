@@ -0,0 +1,5 @@
+SELECT
+  COUNT(*) FILTER (WHERE username IS NOT NULL) AS authenticated,
+  COUNT(*) FILTER (WHERE username IS NULL) AS anonymous
+FROM credentials.session_info
+WHERE expiration_date > $1;
@@ -0,0 +1,57 @@
+-- Existing sessions have no CSRF token, so every user has to log in again.
+DELETE FROM credentials.session_info;
+
+ALTER TABLE credentials.session_info
+  ADD COLUMN IF NOT EXISTS csrf_token VARCHAR NOT NULL;
+
+ALTER TABLE credentials.auth_info
+  ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN IF NOT EXISTS password_reset_required BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN IF NOT EXISTS suspended_until TIMESTAMP,
+  ADD COLUMN IF NOT EXISTS suspension_reason VARCHAR,
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc'),
+  ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
+
+CREATE TABLE IF NOT EXISTS credentials.invites (
+  invite_id BIGSERIAL PRIMARY KEY,
+  code VARCHAR UNIQUE NOT NULL,
+  created_by VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc'),
+  expires_at TIMESTAMP,
+  max_uses INTEGER NOT NULL,
+  uses INTEGER NOT NULL DEFAULT 0,
+  permissions VARCHAR
+);
+
+CREATE SCHEMA IF NOT EXISTS audit;
+
+CREATE TABLE IF NOT EXISTS audit.events (
+  event_id BIGSERIAL PRIMARY KEY,
+  occurred_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc'),
+  event_type VARCHAR NOT NULL,
+  actor VARCHAR,
+  target VARCHAR,
+  ip_address VARCHAR,
+  user_agent VARCHAR,
+  details VARCHAR
+);
+
+CREATE INDEX IF NOT EXISTS events_actor_idx ON audit.events (actor);
+CREATE INDEX IF NOT EXISTS events_target_idx ON audit.events (target);
+CREATE INDEX IF NOT EXISTS events_occurred_at_idx ON audit.events (occurred_at);
+
+CREATE OR REPLACE FUNCTION audit.reject_modification() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audit.events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS events_append_only ON audit.events;
+CREATE TRIGGER events_append_only
+BEFORE UPDATE OR DELETE ON audit.events
+FOR EACH ROW EXECUTE FUNCTION audit.reject_modification();
+
+DROP TRIGGER IF EXISTS events_no_truncate ON audit.events;
+CREATE TRIGGER events_no_truncate
+BEFORE TRUNCATE ON audit.events
+FOR EACH STATEMENT EXECUTE FUNCTION audit.reject_modification();
@@ -0,0 +1,7 @@
+-- Sessions are created lazily, anonymous ones are limited per client address.
+ALTER TABLE credentials.session_info
+  ADD COLUMN client_ip VARCHAR;
+
+CREATE INDEX session_info_anonymous_client_ip
+  ON credentials.session_info (client_ip)
+  WHERE username IS NULL;
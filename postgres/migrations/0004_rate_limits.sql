@@ -0,0 +1,12 @@
+-- Token buckets shared by every instance when rate limits are stored in database.
+CREATE SCHEMA IF NOT EXISTS rate_limits;
+
+CREATE TABLE rate_limits.buckets (
+  bucket_key VARCHAR PRIMARY KEY,
+  tokens DOUBLE PRECISION NOT NULL,
+  allowed BOOLEAN NOT NULL,
+  updated_at TIMESTAMP NOT NULL,
+  full_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX buckets_full_at_idx ON rate_limits.buckets (full_at);
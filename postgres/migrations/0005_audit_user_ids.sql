@@ -0,0 +1,26 @@
+-- Usernames become free again once an account is removed, so events are
+-- tied to accounts through an id which is never reused.
+ALTER TABLE credentials.auth_info
+  ADD COLUMN IF NOT EXISTS user_id BIGSERIAL UNIQUE;
+
+ALTER TABLE audit.events
+  ADD COLUMN IF NOT EXISTS actor_id BIGINT,
+  ADD COLUMN IF NOT EXISTS target_id BIGINT;
+
+-- Events older than the account itself belonged to a previous owner of the name.
+ALTER TABLE audit.events DISABLE TRIGGER events_append_only;
+
+UPDATE audit.events
+SET actor_id = auth_info.user_id
+FROM credentials.auth_info
+WHERE auth_info.username = events.actor AND events.occurred_at >= auth_info.created_at;
+
+UPDATE audit.events
+SET target_id = auth_info.user_id
+FROM credentials.auth_info
+WHERE auth_info.username = events.target AND events.occurred_at >= auth_info.created_at;
+
+ALTER TABLE audit.events ENABLE TRIGGER events_append_only;
+
+CREATE INDEX IF NOT EXISTS events_actor_id_idx ON audit.events (actor_id);
+CREATE INDEX IF NOT EXISTS events_target_id_idx ON audit.events (target_id);
@@ -0,0 +1,10 @@
+-- Existing hashes were computed with the parameters which used to be built in.
+ALTER TABLE credentials.auth_info
+  ADD COLUMN IF NOT EXISTS memory_blocks BIGINT NOT NULL DEFAULT 15360,
+  ADD COLUMN IF NOT EXISTS iterations BIGINT NOT NULL DEFAULT 2,
+  ADD COLUMN IF NOT EXISTS parallelism BIGINT NOT NULL DEFAULT 1;
+
+ALTER TABLE credentials.auth_info
+  ALTER COLUMN memory_blocks DROP DEFAULT,
+  ALTER COLUMN iterations DROP DEFAULT,
+  ALTER COLUMN parallelism DROP DEFAULT;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS public.schema_migrations (
+  version BIGINT PRIMARY KEY,
+  name VARCHAR NOT NULL,
+  checksum BYTEA NOT NULL,
+  applied_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc')
+);
@@ -0,0 +1,2 @@
+SELECT MAX(version) AS version
+FROM public.schema_migrations;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET suspended_until = NULL, suspension_reason = NULL
+WHERE username = $1;
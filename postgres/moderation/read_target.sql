@@ -0,0 +1,4 @@
+SELECT permissions
+FROM credentials.auth_info
+WHERE username = $1
+FOR UPDATE;
@@ -0,0 +1,3 @@
+UPDATE credentials.auth_info
+SET suspended_until = $2, suspension_reason = $3
+WHERE username = $1;
@@ -0,0 +1,2 @@
+DELETE FROM rate_limits.buckets
+WHERE full_at <= NOW() AT TIME ZONE 'utc';
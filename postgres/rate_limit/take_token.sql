@@ -0,0 +1,35 @@
+WITH request AS (
+  SELECT
+    $1::VARCHAR AS bucket_key,
+    $2::DOUBLE PRECISION AS burst,
+    $3::DOUBLE PRECISION AS per_second,
+    NOW() AT TIME ZONE 'utc' AS now
+)
+INSERT INTO rate_limits.buckets(bucket_key, tokens, allowed, updated_at, full_at)
+SELECT
+  request.bucket_key,
+  request.burst - 1,
+  TRUE,
+  request.now,
+  request.now + make_interval(secs => 1 / request.per_second)
+FROM request
+ON CONFLICT (bucket_key) DO UPDATE SET (tokens, allowed, updated_at, full_at) = (
+  SELECT
+    taken.tokens,
+    taken.allowed,
+    request.now,
+    request.now + make_interval(secs => (request.burst - taken.tokens) / request.per_second)
+  FROM request
+  CROSS JOIN LATERAL (
+    SELECT LEAST(
+      request.burst,
+      rate_limits.buckets.tokens + EXTRACT(EPOCH FROM request.now - rate_limits.buckets.updated_at)::DOUBLE PRECISION * request.per_second
+    ) AS tokens
+  ) refilled
+  CROSS JOIN LATERAL (
+    SELECT
+      CASE WHEN refilled.tokens >= 1 THEN refilled.tokens - 1 ELSE refilled.tokens END AS tokens,
+      refilled.tokens >= 1 AS allowed
+  ) taken
+)
+RETURNING tokens, allowed;
@@ -1,2 +1,7 @@
-INSERT INTO credentials.session_info(session_id, expiration_date, username)
-VALUES ($1, $2, $3);
+INSERT INTO credentials.session_info(session_id, expiration_date, username, csrf_token, client_ip)
+SELECT $1, $2, $3, $4, $5::VARCHAR
+WHERE $6::BIGINT = 0 OR (
+  SELECT COUNT(*)
+  FROM credentials.session_info
+  WHERE client_ip = $5::VARCHAR AND username IS NULL AND expiration_date > $7
+) < $6::BIGINT;
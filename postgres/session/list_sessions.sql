@@ -0,0 +1,4 @@
+SELECT session_id, username, client_ip, expiration_date
+FROM credentials.session_info
+WHERE ($1::VARCHAR IS NULL OR username = $1::VARCHAR) AND expiration_date > $2
+ORDER BY expiration_date DESC;
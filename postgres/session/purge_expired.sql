@@ -0,0 +1,2 @@
+DELETE FROM credentials.session_info
+WHERE expiration_date <= $1;
@@ -1,3 +1,3 @@
-SELECT permissions
+SELECT permissions, disabled, password_reset_required, suspended_until, suspension_reason
 FROM credentials.auth_info
 WHERE username = $1;
@@ -0,0 +1,2 @@
+DELETE FROM credentials.session_info
+WHERE username = $1;
@@ -0,0 +1,3 @@
+UPDATE credentials.session_info
+SET session_id = $1, csrf_token = $2, username = $3, expiration_date = $4
+WHERE session_id = $5;
@@ -1,3 +0,0 @@
-UPDATE credential.session_info
-SET username=$1, expiration_date=$2
-WHERE session_id=$3;
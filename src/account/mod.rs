@@ -0,0 +1,182 @@
+mod service;
+
+use std::sync::Arc;
+
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+use sqlx::{query, PgPool, Row};
+use tokio::sync::watch;
+use utoipa::OpenApi;
+
+use crate::{
+    audit::{AuditEvent, EventType},
+    config::Config,
+    database::Database,
+};
+
+pub use service::restore_account;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    service::export_data,
+    service::delete_account,
+    service::restore_account
+))]
+pub struct ApiDoc;
+
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+pub fn routes() -> Router {
+    Router::new()
+        .route("/", delete(service::delete_account))
+        .route("/export", get(service::export_data))
+}
+
+/// Permanently removes accounts whose grace period has passed.
+pub async fn purge_deleted_accounts(
+    database: &PgPool,
+    grace_period: chrono::Duration,
+) -> Result<u64, sqlx::Error> {
+    let list_stmt = include_str!("../../postgres/account/list_purgeable.sql");
+    let purge_stmt = include_str!("../../postgres/account/purge_deleted.sql");
+    let deadline = chrono::Utc::now().naive_utc() - grace_period;
+
+    let mut transaction = database.begin().await?;
+
+    let usernames = query(list_stmt)
+        .bind(deadline)
+        .fetch_all(&mut transaction)
+        .await?
+        .iter()
+        .map(|row| row.try_get("username"))
+        .collect::<Result<Vec<String>, _>>()?;
+
+    // Recorded first, while ids of the accounts can still be looked up.
+    for username in usernames.iter() {
+        AuditEvent::new(EventType::AccountDeleted)
+            .target(username)
+            .details("grace period has passed")
+            .record(&mut transaction)
+            .await?;
+    }
+
+    let purged = query(purge_stmt)
+        .bind(&usernames)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    transaction.commit().await?;
+
+    Ok(purged)
+}
+
+/// Background task periodically purging deleted accounts,
+/// until shutdown is signalled.
+pub async fn purge_task(
+    database: Arc<Database>,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let grace_period = config.accounts.deletion_grace_period();
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        match purge_deleted_accounts(database.writer(), grace_period).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged [{}] deleted accounts.", purged),
+            Err(error) => {
+                tracing::error!(
+                    "Error occured while purging deleted accounts. Error = [{}]",
+                    error
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::{query, query_scalar};
+
+    use super::purge_deleted_accounts;
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn deleted_account_cannot_log_in() {
+        let app = TestApp::spawn().await;
+        let mut client = app.logged_in("alice", "correct horse").await;
+
+        let wrong_password = json!({ "password": "battery staple" });
+        assert_eq!(
+            client.delete("/me", wrong_password).await.code(),
+            "InvalidCredentials"
+        );
+
+        let deleted = client
+            .delete("/me", json!({ "password": "correct horse" }))
+            .await;
+        assert_eq!(deleted.status, 200);
+        assert!(deleted.body["purge_after"].is_string());
+
+        assert_eq!(
+            app.client().login("alice", "correct horse").await.code(),
+            "InvalidCredentials"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn deletion_events_keep_account_ids() {
+        let app = TestApp::with_config(|config| config.accounts.deletion_grace_hours = 0).await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+
+        let mut alice = app.logged_in("alice", "correct horse").await;
+        let deletion = json!({ "password": "correct horse" });
+        assert_eq!(alice.delete("/me", deletion).await.status, 200);
+
+        app.client().signup("bob", "correct horse").await;
+        let deleted = admin.delete("/admin/users/bob", json!({})).await;
+        assert_eq!(deleted.status, 200);
+
+        app.client().signup("carol", "correct horse").await;
+        let mark_stmt = include_str!("../../postgres/account/mark_deleted.sql");
+        query(mark_stmt)
+            .bind("carol")
+            .bind(chrono::Utc::now().naive_utc())
+            .execute(app.database())
+            .await
+            .unwrap();
+        let purged = purge_deleted_accounts(app.database(), chrono::Duration::zero()).await;
+        assert_eq!(purged.unwrap(), 1);
+
+        let targets: Vec<(String, Option<i64>)> = sqlx::query_as(
+            "SELECT target, target_id FROM audit.events \
+            WHERE event_type = 'AccountDeleted' ORDER BY target",
+        )
+        .fetch_all(app.database())
+        .await
+        .unwrap();
+        assert_eq!(
+            targets.iter().map(|(target, _)| target).collect::<Vec<_>>(),
+            ["alice", "bob", "carol"]
+        );
+        assert!(targets.iter().all(|(_, target_id)| target_id.is_some()));
+
+        let remaining: i64 = query_scalar("SELECT COUNT(*) FROM credentials.auth_info")
+            .fetch_one(app.database())
+            .await
+            .unwrap();
+        assert_eq!(remaining, 1);
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{header::CONTENT_DISPOSITION, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{postgres::PgRow, query, PgPool, Row};
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{self, AuditLog, EventType},
+    auth::{self, AdminGuard, AuthError, Hasher},
+    config::Config,
+    database::Database,
+    error::{ApiResult, AppError, Problem},
+    extract::{ValidJson, ValidPath},
+    session::{self, ExistingSession, SessionCache},
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeletionForm {
+    password: String,
+}
+
+#[derive(Serialize)]
+struct Profile {
+    username: String,
+    permissions: String,
+    created_at: NaiveDateTime,
+    disabled: bool,
+    password_reset_required: bool,
+    suspended_until: Option<NaiveDateTime>,
+    suspension_reason: Option<String>,
+}
+
+impl Profile {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Profile {
+            username: row.try_get("username")?,
+            permissions: row.try_get("permissions")?,
+            created_at: row.try_get("created_at")?,
+            disabled: row.try_get("disabled")?,
+            password_reset_required: row.try_get("password_reset_required")?,
+            suspended_until: row.try_get("suspended_until")?,
+            suspension_reason: row.try_get("suspension_reason")?,
+        })
+    }
+}
+
+/// Everything which is stored about given user.
+async fn collect_user_data(database: &PgPool, username: &str) -> Result<Value, sqlx::Error> {
+    let profile_stmt = include_str!("../../postgres/account/read_profile.sql");
+    let profile = query(profile_stmt)
+        .bind(username)
+        .fetch_one(database)
+        .await
+        .and_then(|row| Profile::from_row(&row))?;
+
+    let sessions_stmt = include_str!("../../postgres/admin/read_user_sessions.sql");
+    let sessions = query(sessions_stmt)
+        .bind(username)
+        .fetch_all(database)
+        .await?
+        .iter()
+        .map(|row| row.try_get::<NaiveDateTime, _>("expiration_date"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let audit_events = audit::all_user_events(database, username).await?;
+
+    Ok(json!({
+        "exported_at": chrono::Utc::now().naive_utc(),
+        "profile": profile,
+        "sessions": sessions
+            .into_iter()
+            .map(|expiration_date| json!({ "expiration_date": expiration_date }))
+            .collect::<Vec<_>>(),
+        "audit_events": audit_events
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/export",
+    tag = "account",
+    responses(
+        (status = 200, description = "Everything stored about the logged in user, as an attachment.", body = Object,
+            example = json!({ "exported_at": "2024-01-01T00:00:00", "profile": {}, "sessions": [], "audit_events": [] })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = []))
+)]
+pub async fn export_data(
+    ExistingSession(session_info): ExistingSession,
+    database: Extension<Arc<Database>>,
+) -> Result<Response, AppError> {
+    let session_info = session_info.ok_or(AppError::NotLoggedIn)?;
+    let username = session_info.username().ok_or(AppError::NotLoggedIn)?;
+    let data = collect_user_data(database.writer(), username).await?;
+
+    Ok((
+        StatusCode::OK,
+        // Usernames are not restricted to characters safe in a header.
+        [(
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"budgeters-export.json\"",
+        )],
+        Json(data),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me",
+    tag = "account",
+    request_body = DeletionForm,
+    responses(
+        (status = 200, description = "Account has been deleted, `purge_after` is null when it was removed immediately.", body = Object,
+            example = json!({ "error": "None", "purge_after": "2024-01-31T00:00:00" })),
+        (status = 401, response = Problem),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+/// Deletes account of the logged in user. Account is only marked as deleted
+/// and permanently removed after the grace period, unless it is zero.
+pub async fn delete_account(
+    deletion_form: ValidJson<DeletionForm>,
+    ExistingSession(session_info): ExistingSession,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    hasher: Extension<Arc<Hasher>>,
+    config: Extension<Arc<Config>>,
+    audit: AuditLog,
+) -> ApiResult {
+    let session_info = session_info.ok_or(AppError::NotLoggedIn)?;
+    let username = session_info.username().ok_or(AppError::NotLoggedIn)?;
+
+    // Users who cannot log in anymore are still allowed to leave.
+    match auth::verify_credentials(
+        database.writer(),
+        hasher.as_ref(),
+        username,
+        &deletion_form.password,
+    )
+    .await
+    {
+        Ok(_) | Err(AuthError::AccountDisabled) | Err(AuthError::Suspended { .. }) => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let grace_period = config.accounts.deletion_grace_period();
+    let purge_after = (grace_period > chrono::Duration::zero()).then(|| now + grace_period);
+
+    let mut transaction = database.writer().begin().await?;
+
+    match purge_after {
+        Some(purge_after) => {
+            let mark_stmt = include_str!("../../postgres/account/mark_deleted.sql");
+            query(mark_stmt)
+                .bind(username)
+                .bind(now)
+                .execute(&mut transaction)
+                .await?;
+
+            audit
+                .event(EventType::AccountDeletionRequested)
+                .actor(username)
+                .details(format!("purge_after = {purge_after}"))
+                .record(&mut transaction)
+                .await?;
+        }
+        None => {
+            // Recorded first, while the account's id can still be looked up.
+            audit
+                .event(EventType::AccountDeleted)
+                .actor(username)
+                .target(username)
+                .record(&mut transaction)
+                .await?;
+
+            let delete_stmt = include_str!("../../postgres/account/delete_account.sql");
+            query(delete_stmt)
+                .bind(username)
+                .execute(&mut transaction)
+                .await?;
+        }
+    }
+
+    session::remove_user_sessions(username, &mut transaction).await?;
+    cache.commit_invalidation(username, transaction).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None",
+            "purge_after": purge_after
+        })),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{username}/restore",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Account is no longer marked as deleted.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn restore_account(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    let mut transaction = database.writer().begin().await?;
+
+    let restore_stmt = include_str!("../../postgres/account/restore_account.sql");
+    let restored = query(restore_stmt)
+        .bind(&username)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if restored == 0 {
+        transaction.rollback().await?;
+        return Err(AppError::DeletedAccountNotFound);
+    }
+
+    audit
+        .event(EventType::AccountRestored)
+        .actor(guard.username())
+        .target(&username)
+        .record(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
+}
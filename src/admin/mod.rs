@@ -0,0 +1,110 @@
+mod service;
+
+use axum::{
+    routing::{delete, get, post, put},
+    Router,
+};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        service::list_users,
+        service::user_details,
+        service::change_permissions,
+        service::disable_user,
+        service::enable_user,
+        service::force_password_reset,
+        service::delete_user,
+        service::revoke_sessions
+    ),
+    components(schemas(service::UserOverview))
+)]
+pub struct ApiDoc;
+
+pub fn routes() -> Router {
+    Router::new()
+        .route("/users", get(service::list_users))
+        .route(
+            "/users/:username",
+            get(service::user_details).delete(service::delete_user),
+        )
+        .route(
+            "/users/:username/permissions",
+            put(service::change_permissions),
+        )
+        .route("/users/:username/disable", post(service::disable_user))
+        .route("/users/:username/enable", post(service::enable_user))
+        .route(
+            "/users/:username/password-reset",
+            post(service::force_password_reset),
+        )
+        .route(
+            "/users/:username/sessions",
+            delete(service::revoke_sessions),
+        )
+        .route(
+            "/users/:username/restore",
+            post(crate::account::restore_account),
+        )
+        .route("/audit", get(crate::audit::list_events))
+        .route(
+            "/invites",
+            get(crate::invites::list_invites).post(crate::invites::create_invite),
+        )
+        .route("/invites/:invite_id", delete(crate::invites::revoke_invite))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn search_matches_wildcards_literally() {
+        let app = TestApp::spawn().await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+        app.client().signup("a_b", "correct horse").await;
+        app.client().signup("axb", "correct horse").await;
+
+        let found = admin.get("/admin/users?search=a_").await;
+        assert_eq!(found.body["total"], 1);
+        assert_eq!(found.body["users"][0]["username"], "a_b");
+
+        let found = admin.get("/admin/users?search=%25").await;
+        assert_eq!(found.body["total"], 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn admins_cannot_reset_own_password() {
+        let app = TestApp::spawn().await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+
+        let reset = admin
+            .post("/admin/users/admin/password-reset", json!({}))
+            .await;
+        assert_eq!(reset.code(), "CannotModifyOwnAccount");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn revoking_sessions_of_unknown_user_fails() {
+        let app = TestApp::spawn().await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+
+        let revoked = admin
+            .delete("/admin/users/nobody/sessions", json!({}))
+            .await;
+        assert_eq!(revoked.status, 404);
+        assert_eq!(revoked.code(), "UserNotFound");
+
+        let events = admin.get("/admin/audit?event_type=SessionsRevoked").await;
+        assert_eq!(events.body["total"], 0);
+    }
+}
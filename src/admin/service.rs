@@ -0,0 +1,441 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{
+    postgres::{PgArguments, PgRow},
+    query, PgPool, Postgres, Row,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{AuditEvent, AuditLog, EventType},
+    auth::{AdminGuard, Permissions},
+    database::Database,
+    error::{ApiResult, AppError, Problem},
+    extract::{ValidJson, ValidPath, ValidQuery},
+    pagination::Pagination,
+    session::{self, SessionCache},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct UserQuery {
+    /// Part of the username to look for.
+    search: Option<String>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PermissionsForm {
+    permissions: Permissions,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserOverview {
+    username: String,
+    permissions: Permissions,
+    disabled: bool,
+    password_reset_required: bool,
+    created_at: NaiveDateTime,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+impl UserOverview {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        let permissions: String = row.try_get("permissions")?;
+
+        Ok(UserOverview {
+            username: row.try_get("username")?,
+            permissions: permissions
+                .parse()
+                .map_err(|e: &str| sqlx::Error::Decode(e.into()))?,
+            disabled: row.try_get("disabled")?,
+            password_reset_required: row.try_get("password_reset_required")?,
+            created_at: row.try_get("created_at")?,
+            deleted_at: row.try_get("deleted_at")?,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    params(UserQuery),
+    responses(
+        (status = 200, description = "Page of matching `UserOverview`s, ordered by username.", body = Object,
+            example = json!({ "users": [], "page": 1, "per_page": 20, "total": 0 })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = []))
+)]
+pub async fn list_users(
+    ValidQuery(user_query): ValidQuery<UserQuery>,
+    database: Extension<Arc<Database>>,
+    _guard: AdminGuard,
+) -> ApiResult {
+    let pagination = Pagination::new(user_query.page, user_query.per_page);
+
+    let count_stmt = include_str!("../../postgres/admin/count_users.sql");
+    let total: i64 = query(count_stmt)
+        .bind(&user_query.search)
+        .fetch_one(database.writer())
+        .await?
+        .try_get("total")?;
+
+    let list_stmt = include_str!("../../postgres/admin/list_users.sql");
+    let users = query(list_stmt)
+        .bind(&user_query.search)
+        .bind(pagination.limit())
+        .bind(pagination.offset())
+        .fetch_all(database.writer())
+        .await?
+        .iter()
+        .map(UserOverview::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "users": users,
+            "page": pagination.page,
+            "per_page": pagination.per_page,
+            "total": total
+        })),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users/{username}",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Account of the user with expiration dates of their sessions.", body = Object,
+            example = json!({ "user": {}, "sessions": [{ "expiration_date": "2024-01-01T00:00:00" }] })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = []))
+)]
+pub async fn user_details(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    _guard: AdminGuard,
+) -> ApiResult {
+    let read_stmt = include_str!("../../postgres/admin/read_user.sql");
+    let user = match query(read_stmt)
+        .bind(&username)
+        .fetch_optional(database.writer())
+        .await?
+    {
+        Some(row) => UserOverview::from_row(&row)?,
+        None => return Err(AppError::UserNotFound),
+    };
+
+    let sessions_stmt = include_str!("../../postgres/admin/read_user_sessions.sql");
+    let sessions = query(sessions_stmt)
+        .bind(&username)
+        .fetch_all(database.writer())
+        .await?
+        .iter()
+        .map(|row| row.try_get::<NaiveDateTime, _>("expiration_date"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "user": user,
+            "sessions": sessions
+                .into_iter()
+                .map(|expiration_date| json!({ "expiration_date": expiration_date }))
+                .collect::<Vec<_>>()
+        })),
+    ))
+}
+
+/// Executes given statement modifying user's account together with
+/// its audit entries in single transaction. Fails with `UserNotFound`
+/// if user targeted by the event does not exist.
+async fn modify_user(
+    database: &PgPool,
+    cache: &SessionCache,
+    audit: &AuditLog,
+    statement: sqlx::query::Query<'_, Postgres, PgArguments>,
+    terminate_sessions: bool,
+    event: AuditEvent<'_>,
+) -> ApiResult {
+    let mut transaction = database.begin().await?;
+
+    // Events are recorded before the statement, which may delete the
+    // user whose id they refer to. They are rolled back with it.
+    event.record(&mut transaction).await?;
+
+    if let (true, Some(target)) = (terminate_sessions, event.target) {
+        let revoked = session::remove_user_sessions(target, &mut transaction).await?;
+        let mut revocation = audit
+            .event(EventType::SessionsRevoked)
+            .target(target)
+            .details(format!("count = {revoked}"));
+        revocation.actor = event.actor;
+
+        revocation.record(&mut transaction).await?;
+    }
+
+    if statement.execute(&mut transaction).await?.rows_affected() == 0 {
+        transaction.rollback().await?;
+        return Err(AppError::UserNotFound);
+    }
+
+    match event.target {
+        Some(target) => cache.commit_invalidation(target, transaction).await?,
+        None => transaction.commit().await?,
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{username}/permissions",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    request_body = PermissionsForm,
+    responses(
+        (status = 200, description = "Permissions have been changed.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn change_permissions(
+    ValidPath(username): ValidPath<String>,
+    permissions_form: ValidJson<PermissionsForm>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    if guard.username() == username {
+        return Err(AppError::CannotModifyOwnAccount);
+    }
+
+    let update_stmt = include_str!("../../postgres/admin/update_permissions.sql");
+    let statement = query(update_stmt)
+        .bind(&username)
+        .bind(permissions_form.permissions.to_string());
+
+    let event = audit
+        .event(EventType::PermissionsChanged)
+        .actor(guard.username())
+        .target(&username)
+        .details(permissions_form.permissions.to_string());
+
+    modify_user(database.writer(), &cache, &audit, statement, false, event).await
+}
+
+async fn set_disabled(
+    username: String,
+    disabled: bool,
+    database: &PgPool,
+    cache: &SessionCache,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    if guard.username() == username {
+        return Err(AppError::CannotModifyOwnAccount);
+    }
+
+    let update_stmt = include_str!("../../postgres/admin/set_disabled.sql");
+    let statement = query(update_stmt).bind(&username).bind(disabled);
+
+    let event_type = if disabled {
+        EventType::AccountDisabled
+    } else {
+        EventType::AccountEnabled
+    };
+    let event = audit
+        .event(event_type)
+        .actor(guard.username())
+        .target(&username);
+
+    modify_user(database, cache, &audit, statement, disabled, event).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{username}/disable",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Account has been disabled and its sessions revoked.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn disable_user(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    set_disabled(username, true, database.writer(), &cache, audit, guard).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{username}/enable",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Account has been enabled.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn enable_user(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    set_disabled(username, false, database.writer(), &cache, audit, guard).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{username}/password-reset",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "User has to change password after logging in again.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn force_password_reset(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    if guard.username() == username {
+        return Err(AppError::CannotModifyOwnAccount);
+    }
+
+    let update_stmt = include_str!("../../postgres/admin/require_password_reset.sql");
+    let statement = query(update_stmt).bind(&username);
+
+    let event = audit
+        .event(EventType::PasswordResetForced)
+        .actor(guard.username())
+        .target(&username);
+
+    modify_user(database.writer(), &cache, &audit, statement, true, event).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{username}",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Account has been deleted.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn delete_user(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    if guard.username() == username {
+        return Err(AppError::CannotModifyOwnAccount);
+    }
+
+    let delete_stmt = include_str!("../../postgres/admin/delete_user.sql");
+    let statement = query(delete_stmt).bind(&username);
+
+    let event = audit
+        .event(EventType::AccountDeleted)
+        .actor(guard.username())
+        .target(&username);
+
+    modify_user(database.writer(), &cache, &audit, statement, true, event).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{username}/sessions",
+    tag = "admin",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Every session of the user has been removed.", body = Object,
+            example = json!({ "error": "None", "revoked": 2 })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn revoke_sessions(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    let mut transaction = database.writer().begin().await?;
+
+    // Keeps the user from being deleted until the revocation is recorded.
+    let lock_stmt = include_str!("../../postgres/admin/lock_user.sql");
+    if query(lock_stmt)
+        .bind(&username)
+        .fetch_optional(&mut transaction)
+        .await?
+        .is_none()
+    {
+        transaction.rollback().await?;
+        return Err(AppError::UserNotFound);
+    }
+
+    let revoked = session::remove_user_sessions(&username, &mut transaction).await?;
+    audit
+        .event(EventType::SessionsRevoked)
+        .actor(guard.username())
+        .target(&username)
+        .details(format!("count = {revoked}"))
+        .record(&mut transaction)
+        .await?;
+    cache.commit_invalidation(&username, transaction).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None",
+            "revoked": revoked
+        })),
+    ))
+}
@@ -0,0 +1,285 @@
+mod service;
+
+use std::{fmt::Display, net::SocketAddr, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequest, RequestParts},
+    http::header::USER_AGENT,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgExecutor, query};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{database::Database, error::AppError};
+
+pub use service::{all_user_events, list_events, user_activity};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(service::list_events, service::user_activity),
+    components(schemas(service::StoredEvent))
+)]
+pub struct ApiDoc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum EventType {
+    Signup,
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    PermissionsChanged,
+    SessionsRevoked,
+    AccountDisabled,
+    AccountEnabled,
+    PasswordResetForced,
+    AccountDeleted,
+    AccountSuspended,
+    SuspensionLifted,
+    AdminBootstrapped,
+    InviteCreated,
+    InviteRevoked,
+    AccountDeletionRequested,
+    AccountRestored,
+    AccountCreated,
+}
+
+impl Display for EventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result_string = match self {
+            EventType::Signup => "Signup",
+            EventType::LoginSucceeded => "LoginSucceeded",
+            EventType::LoginFailed => "LoginFailed",
+            EventType::Logout => "Logout",
+            EventType::PasswordChanged => "PasswordChanged",
+            EventType::PermissionsChanged => "PermissionsChanged",
+            EventType::SessionsRevoked => "SessionsRevoked",
+            EventType::AccountDisabled => "AccountDisabled",
+            EventType::AccountEnabled => "AccountEnabled",
+            EventType::PasswordResetForced => "PasswordResetForced",
+            EventType::AccountDeleted => "AccountDeleted",
+            EventType::AccountSuspended => "AccountSuspended",
+            EventType::SuspensionLifted => "SuspensionLifted",
+            EventType::AdminBootstrapped => "AdminBootstrapped",
+            EventType::InviteCreated => "InviteCreated",
+            EventType::InviteRevoked => "InviteRevoked",
+            EventType::AccountDeletionRequested => "AccountDeletionRequested",
+            EventType::AccountRestored => "AccountRestored",
+            EventType::AccountCreated => "AccountCreated",
+        };
+
+        write!(f, "{result_string}")
+    }
+}
+
+impl FromStr for EventType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Signup" => Ok(EventType::Signup),
+            "LoginSucceeded" => Ok(EventType::LoginSucceeded),
+            "LoginFailed" => Ok(EventType::LoginFailed),
+            "Logout" => Ok(EventType::Logout),
+            "PasswordChanged" => Ok(EventType::PasswordChanged),
+            "PermissionsChanged" => Ok(EventType::PermissionsChanged),
+            "SessionsRevoked" => Ok(EventType::SessionsRevoked),
+            "AccountDisabled" => Ok(EventType::AccountDisabled),
+            "AccountEnabled" => Ok(EventType::AccountEnabled),
+            "PasswordResetForced" => Ok(EventType::PasswordResetForced),
+            "AccountDeleted" => Ok(EventType::AccountDeleted),
+            "AccountSuspended" => Ok(EventType::AccountSuspended),
+            "SuspensionLifted" => Ok(EventType::SuspensionLifted),
+            "AdminBootstrapped" => Ok(EventType::AdminBootstrapped),
+            "InviteCreated" => Ok(EventType::InviteCreated),
+            "InviteRevoked" => Ok(EventType::InviteRevoked),
+            "AccountDeletionRequested" => Ok(EventType::AccountDeletionRequested),
+            "AccountRestored" => Ok(EventType::AccountRestored),
+            "AccountCreated" => Ok(EventType::AccountCreated),
+            _ => Err("Given string does not represent audit event type."),
+        }
+    }
+}
+
+/// Single entry of the audit log. Entries are only ever
+/// appended, they are never updated nor removed.
+///
+/// Actor and target are also stored as ids of their accounts, so that
+/// a later owner of a removed account's username does not see its events.
+pub struct AuditEvent<'a> {
+    pub event_type: EventType,
+    pub actor: Option<&'a str>,
+    pub target: Option<&'a str>,
+    pub ip_address: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub details: Option<String>,
+}
+
+impl<'a> AuditEvent<'a> {
+    pub fn new(event_type: EventType) -> Self {
+        AuditEvent {
+            event_type,
+            actor: None,
+            target: None,
+            ip_address: None,
+            user_agent: None,
+            details: None,
+        }
+    }
+
+    pub fn actor(mut self, actor: &'a str) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    pub fn target(mut self, target: &'a str) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    /// Writes event using given executor, so it can
+    /// be a part of the transaction performing the action.
+    pub async fn record<'e, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: PgExecutor<'e>,
+    {
+        let insert_stmt = include_str!("../../postgres/audit/insert_event.sql");
+
+        query(insert_stmt)
+            .bind(self.event_type.to_string())
+            .bind(self.actor)
+            .bind(self.target)
+            .bind(self.ip_address)
+            .bind(self.user_agent)
+            .bind(&self.details)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Audit log bound to the request being handled. Events
+/// created through it carry client's IP address and user agent.
+pub struct AuditLog {
+    database: Arc<Database>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl AuditLog {
+    pub fn event(&self, event_type: EventType) -> AuditEvent<'_> {
+        AuditEvent {
+            ip_address: self.ip_address.as_deref(),
+            user_agent: self.user_agent.as_deref(),
+            ..AuditEvent::new(event_type)
+        }
+    }
+
+    /// Records event outside of any transaction. Failure to do so
+    /// is only logged, so it does not affect handled request.
+    pub async fn record(&self, event: AuditEvent<'_>) {
+        if let Err(error) = event.record(self.database.writer()).await {
+            tracing::error!(
+                "Unable to record audit event [{}]. Error = [{}]",
+                event.event_type,
+                error
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuditLog
+where
+    B: Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let database = req
+            .extensions()
+            .get::<Arc<Database>>()
+            .cloned()
+            .ok_or_else(|| {
+                AppError::Internal(
+                    "Unable to get database from extensions in AuditLog extractor.".into(),
+                )
+            })?;
+
+        let ip_address = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Ok(AuditLog {
+            database,
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::USER_AGENT;
+    use serde_json::{json, Value};
+
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn activity_hides_other_clients_and_previous_owners() {
+        let app = TestApp::with_config(|config| config.accounts.deletion_grace_hours = 0).await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+        admin.set_header(USER_AGENT, "admin browser");
+
+        let mut alice = app.client();
+        alice.set_header(USER_AGENT, "alice browser");
+        alice.signup("alice", "correct horse").await;
+        assert_eq!(
+            admin
+                .delete("/admin/users/alice/sessions", json!({}))
+                .await
+                .status,
+            200
+        );
+        alice.login("alice", "correct horse").await;
+
+        let activity = alice.get("/auth/activity").await;
+        let events = activity.body["events"].as_array().unwrap();
+        let user_agents = |event_type: &str| -> Vec<Value> {
+            events
+                .iter()
+                .filter(|event| event["event_type"] == event_type)
+                .map(|event| event["user_agent"].clone())
+                .collect()
+        };
+        assert_eq!(user_agents("SessionsRevoked"), [Value::Null]);
+        assert_eq!(user_agents("LoginSucceeded"), [json!("alice browser")]);
+
+        let deletion = json!({ "password": "correct horse" });
+        assert_eq!(alice.delete("/me", deletion).await.status, 200);
+
+        let mut new_alice = app.logged_in("alice", "battery staple").await;
+        let activity = new_alice.get("/auth/activity").await;
+        assert_eq!(activity.body["total"], 2);
+        assert!(activity.body["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|event| event["event_type"] != "SessionsRevoked"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgRow, query, PgPool, Row};
+use utoipa::{IntoParams, ToSchema};
+
+use super::EventType;
+use crate::{
+    auth::{AdminGuard, UserGuard},
+    database::Database,
+    error::{ApiResult, Problem},
+    extract::ValidQuery,
+    pagination::Pagination,
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct EventQuery {
+    event_type: Option<EventType>,
+    actor: Option<String>,
+    target: Option<String>,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ActivityQuery {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StoredEvent {
+    event_id: i64,
+    occurred_at: NaiveDateTime,
+    event_type: String,
+    actor: Option<String>,
+    target: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    details: Option<String>,
+}
+
+impl StoredEvent {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(StoredEvent {
+            event_id: row.try_get("event_id")?,
+            occurred_at: row.try_get("occurred_at")?,
+            event_type: row.try_get("event_type")?,
+            actor: row.try_get("actor")?,
+            target: row.try_get("target")?,
+            ip_address: row.try_get("ip_address")?,
+            user_agent: row.try_get("user_agent")?,
+            details: row.try_get("details")?,
+        })
+    }
+}
+
+/// Every event in which given user took part, oldest first.
+pub async fn all_user_events(
+    database: &PgPool,
+    username: &str,
+) -> Result<Vec<StoredEvent>, sqlx::Error> {
+    let list_stmt = include_str!("../../postgres/audit/all_user_events.sql");
+
+    query(list_stmt)
+        .bind(username)
+        .fetch_all(database)
+        .await?
+        .iter()
+        .map(StoredEvent::from_row)
+        .collect()
+}
+
+fn events_page(total: i64, rows: Vec<PgRow>, pagination: Pagination) -> ApiResult {
+    let events = rows
+        .iter()
+        .map(StoredEvent::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "events": events,
+            "page": pagination.page,
+            "per_page": pagination.per_page,
+            "total": total
+        })),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(EventQuery),
+    responses(
+        (status = 200, description = "Page of matching `StoredEvent`s, newest first.", body = Object,
+            example = json!({ "events": [], "page": 1, "per_page": 20, "total": 0 })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = []))
+)]
+pub async fn list_events(
+    ValidQuery(event_query): ValidQuery<EventQuery>,
+    database: Extension<Arc<Database>>,
+    _guard: AdminGuard,
+) -> ApiResult {
+    let pagination = Pagination::new(event_query.page, event_query.per_page);
+    let event_type = event_query.event_type.map(|t| t.to_string());
+
+    let count_stmt = include_str!("../../postgres/audit/count_events.sql");
+    let total = query(count_stmt)
+        .bind(&event_type)
+        .bind(&event_query.actor)
+        .bind(&event_query.target)
+        .bind(event_query.since)
+        .bind(event_query.until)
+        .fetch_one(database.writer())
+        .await?
+        .try_get("total")?;
+
+    let list_stmt = include_str!("../../postgres/audit/list_events.sql");
+    let events = query(list_stmt)
+        .bind(&event_type)
+        .bind(&event_query.actor)
+        .bind(&event_query.target)
+        .bind(event_query.since)
+        .bind(event_query.until)
+        .bind(pagination.limit())
+        .bind(pagination.offset())
+        .fetch_all(database.writer())
+        .await?;
+
+    events_page(total, events, pagination)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/activity",
+    tag = "auth",
+    params(ActivityQuery),
+    responses(
+        (status = 200, description = "Page of the user's `StoredEvent`s, newest first.", body = Object,
+            example = json!({ "events": [], "page": 1, "per_page": 20, "total": 0 })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = []))
+)]
+/// Security-relevant events in which logged in user
+/// took part, either as an actor or as a target.
+pub async fn user_activity(
+    ValidQuery(activity_query): ValidQuery<ActivityQuery>,
+    database: Extension<Arc<Database>>,
+    guard: UserGuard,
+) -> ApiResult {
+    let pagination = Pagination::new(activity_query.page, activity_query.per_page);
+
+    let count_stmt = include_str!("../../postgres/audit/count_user_events.sql");
+    let total = query(count_stmt)
+        .bind(guard.username())
+        .fetch_one(database.writer())
+        .await?
+        .try_get("total")?;
+
+    let list_stmt = include_str!("../../postgres/audit/list_user_events.sql");
+    let events = query(list_stmt)
+        .bind(guard.username())
+        .bind(pagination.limit())
+        .bind(pagination.offset())
+        .fetch_all(database.writer())
+        .await?;
+
+    events_page(total, events, pagination)
+}
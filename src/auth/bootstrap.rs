@@ -0,0 +1,165 @@
+use sqlx::{query, PgPool, Row};
+
+use super::{service::insert_user, AuthError, Hasher, Permissions};
+use crate::{
+    audit::{AuditEvent, EventType},
+    logging, session,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BootstrapOutcome {
+    Created,
+    Promoted,
+}
+
+fn database_error(error: sqlx::Error) -> AuthError {
+    AuthError::DatabaseError(error.to_string())
+}
+
+/// Creates administrator account or, when `promote_existing` is set,
+/// promotes existing user to administrator. Disabled accounts and ones
+/// marked as deleted are never promoted. Unless `force` is set, it refuses
+/// to do anything when any administrator already exists. Password is only
+/// used when new account is created.
+pub async fn bootstrap_admin(
+    database: &PgPool,
+    hasher: &Hasher,
+    username: &str,
+    password: &str,
+    force: bool,
+    promote_existing: bool,
+) -> Result<BootstrapOutcome, AuthError> {
+    let mut transaction = database.begin().await.map_err(database_error)?;
+
+    // Serializes bootstraps of instances starting at the same time.
+    let lock_stmt = include_str!("../../postgres/auth/lock_bootstrap.sql");
+    query(lock_stmt)
+        .execute(&mut transaction)
+        .await
+        .map_err(database_error)?;
+
+    if !force {
+        let exists_stmt = include_str!("../../postgres/auth/admin_exists.sql");
+        let admin_exists: bool = query(exists_stmt)
+            .fetch_one(&mut transaction)
+            .await
+            .and_then(|row| row.try_get("admin_exists"))
+            .map_err(database_error)?;
+
+        if admin_exists {
+            return Err(AuthError::AdminExists);
+        }
+    }
+
+    let promote_stmt = include_str!("../../postgres/auth/promote_admin.sql");
+    let promoted = promote_existing
+        && query(promote_stmt)
+            .bind(username)
+            .bind(Permissions::Admin.to_string())
+            .execute(&mut transaction)
+            .await
+            .map_err(database_error)?
+            .rows_affected()
+            == 1;
+
+    let outcome = if promoted {
+        session::notify_invalidation(username, &mut transaction)
+            .await
+            .map_err(database_error)?;
+
+        BootstrapOutcome::Promoted
+    } else {
+        let password = hasher.process_password(password.as_bytes()).await;
+
+        insert_user(&mut transaction, username, &password, Permissions::Admin).await?;
+
+        BootstrapOutcome::Created
+    };
+
+    AuditEvent::new(EventType::AdminBootstrapped)
+        .target(username)
+        .details(format!("{outcome:?}"))
+        .record(&mut transaction)
+        .await
+        .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(outcome)
+}
+
+/// First boot option. When `BG_BOOTSTRAP_ADMIN` and `BG_BOOTSTRAP_ADMIN_PASSWORD`
+/// env variables are set and there is no administrator yet, it creates one.
+/// Existing account of that name is left alone, since anyone could have
+/// signed up with it before the first deploy.
+pub async fn bootstrap_from_env(database: &PgPool, hasher: &Hasher) {
+    let (username, password) = match (
+        std::env::var("BG_BOOTSTRAP_ADMIN"),
+        std::env::var("BG_BOOTSTRAP_ADMIN_PASSWORD"),
+    ) {
+        (Ok(username), Ok(password)) => (username, password),
+        (Ok(_), Err(_)) => {
+            tracing::warn!("BG_BOOTSTRAP_ADMIN is set without BG_BOOTSTRAP_ADMIN_PASSWORD, skipping admin bootstrap.");
+            return;
+        }
+        _ => return,
+    };
+
+    match bootstrap_admin(database, hasher, &username, &password, false, false).await {
+        Ok(outcome) => {
+            tracing::info!(
+                "Bootstrapped administrator [{}]. Outcome = [{:?}]",
+                logging::redact(&username),
+                outcome
+            )
+        }
+        Err(AuthError::AdminExists) => {
+            tracing::debug!("Administrator already exists, skipping admin bootstrap.")
+        }
+        Err(AuthError::UsernameTaken) => {
+            tracing::error!(
+                "Account [{}] named by BG_BOOTSTRAP_ADMIN already exists and is not an administrator, skipping admin bootstrap. Promote it with `admin create` if it is yours.",
+                logging::redact(&username)
+            )
+        }
+        Err(error) => {
+            tracing::error!("Unable to bootstrap administrator. Error = [{}]", error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+
+    use super::{bootstrap_admin, AuthError, BootstrapOutcome};
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn existing_user_is_not_promoted_by_env_bootstrap() {
+        let app = TestApp::spawn().await;
+        let hasher = app.hasher();
+        let mut squatter = app.logged_in("admin", "squatted").await;
+
+        let bootstrapped =
+            bootstrap_admin(app.database(), &hasher, "admin", "intended", false, false).await;
+        assert!(matches!(bootstrapped, Err(AuthError::UsernameTaken)));
+        assert_eq!(
+            squatter.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+
+        // Explicit promotion skips disabled accounts.
+        let disable_stmt = include_str!("../../postgres/admin/set_disabled.sql");
+        let set_disabled = |disabled| query(disable_stmt).bind("admin").bind(disabled);
+        set_disabled(true).execute(app.database()).await.unwrap();
+
+        let promoted = bootstrap_admin(app.database(), &hasher, "admin", "", false, true).await;
+        assert!(matches!(promoted, Err(AuthError::UsernameTaken)));
+
+        set_disabled(false).execute(app.database()).await.unwrap();
+        let promoted = bootstrap_admin(app.database(), &hasher, "admin", "", false, true).await;
+        assert!(matches!(promoted, Ok(BootstrapOutcome::Promoted)));
+    }
+}
@@ -1,58 +1,141 @@
+use std::sync::Arc;
+
 use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, Params,
 };
+use prometheus::Histogram;
+use tokio::sync::Semaphore;
 
-fn pepper_generator() -> Vec<u8> {
-    vec![12, 61, 178, 12, 3, 87, 12, 225, 17, 143, 162, 8]
-}
+use crate::config::HashingConfig;
 
-const MEMORY_BLOCKS: u32 = 15360; // 5MB
-const ITERATIONS: u32 = 2;
-const PARALLELISM: u32 = 1;
-const HASH_LENGTH: usize = 64;
+pub const HASH_LENGTH: usize = 64;
 
 pub type PasswordHash = [u8; HASH_LENGTH];
 
+/// Newly hashed password with everything needed to check it later. Parameters
+/// are stored with every hash, so that changing them does not break old ones.
+pub struct HashedPassword {
+    pub hash: PasswordHash,
+    pub salt: SaltString,
+    pub params: Params,
+}
+
+impl HashedPassword {
+    /// Parameters as stored in `memory_blocks`, `iterations` and `parallelism` columns.
+    pub fn stored_params(&self) -> (i64, i64, i64) {
+        (
+            i64::from(self.params.m_cost()),
+            i64::from(self.params.t_cost()),
+            i64::from(self.params.p_cost()),
+        )
+    }
+}
+
+/// Parameters read back from the columns written by [`HashedPassword::stored_params`].
+pub fn stored_params(memory_blocks: i64, iterations: i64, parallelism: i64) -> Option<Params> {
+    Params::new(
+        memory_blocks.try_into().ok()?,
+        iterations.try_into().ok()?,
+        parallelism.try_into().ok()?,
+        Some(HASH_LENGTH),
+    )
+    .ok()
+}
+
 #[derive(Clone)]
-pub struct Hasher<'a> {
-    argon2_alg: Argon2<'a>,
+pub struct Hasher {
+    pepper: Arc<[u8]>,
+    params: Params,
+    /// One permit per hash which can be computed in parallel. Hashing runs
+    /// on blocking threads, so the runtime keeps serving other requests.
+    permits: Arc<Semaphore>,
+    duration: Histogram,
 }
 
-impl<'a> Hasher<'a> {
-    pub fn new(key: &'a [u8]) -> Self {
-        let params = Params::new(MEMORY_BLOCKS, ITERATIONS, PARALLELISM, Some(HASH_LENGTH))
-            .expect("Unable to create password hasher.");
+impl Hasher {
+    pub fn new(key: &[u8], config: &HashingConfig, duration: Histogram) -> Self {
+        let params = Params::new(
+            config.memory_blocks,
+            config.iterations,
+            config.parallelism,
+            Some(HASH_LENGTH),
+        )
+        .expect("Unable to create password hasher.");
+        let capacity = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1);
 
         Hasher {
-            argon2_alg: Argon2::new_with_secret(
-                key,
-                argon2::Algorithm::Argon2id,
-                argon2::Version::V0x13,
-                params,
-            )
-            .expect("Unable to create Argon2 structure with provided secret."),
+            pepper: key.into(),
+            params,
+            permits: Arc::new(Semaphore::new(capacity)),
+            duration,
         }
     }
 
-    fn hash_password(&self, password: &[u8], salt: &[u8]) -> PasswordHash {
-        let mut result: PasswordHash = [0; HASH_LENGTH];
+    /// Whether every available thread is busy hashing passwords,
+    /// so that further logins would have to wait.
+    pub fn is_saturated(&self) -> bool {
+        self.permits.available_permits() == 0
+    }
+
+    /// Parameters new passwords are hashed with.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    async fn hash_password(&self, password: &[u8], salt: &[u8], params: Params) -> PasswordHash {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("Password hashing semaphore is never closed.");
+        let (pepper, duration) = (self.pepper.clone(), self.duration.clone());
+        let (password, salt) = (password.to_vec(), salt.to_vec());
+
+        tokio::task::spawn_blocking(move || {
+            let _timer = duration.start_timer();
+            let mut result: PasswordHash = [0; HASH_LENGTH];
 
-        self.argon2_alg
-            .hash_password_into(password, salt, &mut result)
+            Argon2::new_with_secret(
+                &pepper,
+                argon2::Algorithm::Argon2id,
+                argon2::Version::V0x13,
+                params,
+            )
+            .expect("Unable to create Argon2 structure with provided secret.")
+            .hash_password_into(&password, &salt, &mut result)
             .expect("Unable to perform password hashing!");
 
-        result
+            result
+        })
+        .await
+        .expect("Password hashing task panicked.")
     }
-    pub fn process_password(&self, password: &[u8]) -> (PasswordHash, SaltString) {
+
+    pub async fn process_password(&self, password: &[u8]) -> HashedPassword {
         let salt = SaltString::generate(&mut OsRng);
-        let result = self.hash_password(password, salt.as_bytes());
+        let hash = self
+            .hash_password(password, salt.as_bytes(), self.params.clone())
+            .await;
 
-        (result, salt)
+        HashedPassword {
+            hash,
+            salt,
+            params: self.params.clone(),
+        }
     }
 
-    pub fn password_check(&self, password: &[u8], salt: &[u8], hash: &PasswordHash) -> bool {
-        let calculated_hash = self.hash_password(password, salt);
+    /// Checks password against a hash computed with given parameters.
+    pub async fn password_check(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        hash: &PasswordHash,
+        params: &Params,
+    ) -> bool {
+        let calculated_hash = self.hash_password(password, salt, params.clone()).await;
 
         calculated_hash.eq(hash)
     }
@@ -1,17 +1,39 @@
 use std::sync::Arc;
 
-use axum::{
-    extract::{FromRequest, RequestParts},
-    http::StatusCode,
-    Json,
-};
-use sqlx::PgPool;
+use axum::extract::{FromRequest, RequestParts};
 
-use crate::session;
+use crate::{
+    database::Database,
+    error::AppError,
+    session::{self, SessionCache},
+};
 
-use super::Permissions;
+use super::{AccountStatus, Permissions};
 use async_trait::async_trait;
-use serde_json::{json, Value};
+
+fn database<B>(req: &RequestParts<B>) -> Result<&Arc<Database>, AppError> {
+    req.extensions()
+        .get::<Arc<Database>>()
+        .ok_or_else(|| AppError::Internal("Unable to get database in authorization guard.".into()))
+}
+
+fn cache<B>(req: &RequestParts<B>) -> Result<&Arc<SessionCache>, AppError> {
+    req.extensions().get::<Arc<SessionCache>>().ok_or_else(|| {
+        AppError::Internal("Unable to get session cache in authorization guard.".into())
+    })
+}
+
+/// Status of the logged in user, if there is one.
+async fn account_status(
+    session: Option<&session::SessionInfo>,
+    database: &Database,
+    cache: &SessionCache,
+) -> Result<Option<AccountStatus>, AppError> {
+    match session {
+        Some(session_info) => Ok(session_info.read_account_status(database, cache).await?),
+        None => Ok(None),
+    }
+}
 
 pub type LowestGuard = UserGuard;
 pub type HighestGuard = AdminGuard;
@@ -23,73 +45,63 @@ macro_rules! authorization_guards {
     };
 
     ($struct_name:ident, $rights:ident) => {
-        pub struct $struct_name {}
+        pub struct $struct_name {
+            username: String,
+            permissions: Permissions,
+        }
+
+        // Not every guard makes use of all accessors.
+        #[allow(dead_code)]
+        impl $struct_name {
+            /// Name of the user who passed the guard.
+            pub fn username(&self) -> &str {
+                &self.username
+            }
+
+            /// Actual permissions of the user, which may be
+            /// higher than required by the guard.
+            pub fn permissions(&self) -> Permissions {
+                self.permissions
+            }
+        }
 
         #[async_trait]
         impl<B> FromRequest<B> for $struct_name
         where
             B: Send,
         {
-            type Rejection = (StatusCode, Json<Value>);
+            type Rejection = AppError;
 
             async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-                let session_info = match req.extract::<session::SessionInfo>().await {
-                    Ok(session_info) => session_info,
-                    Err(error) => {
-                        tracing::warn!("Error while retriving session info from request. Error = [{}]", error.1);
-
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(json!({"error": "Unable to extract session info."}))
-                        ));
+                let session = req.extract::<session::ExistingSession>().await?.0;
+                let (database, cache) = (database(req)?, cache(req)?);
+
+                match account_status(session.as_ref(), database, cache).await? {
+                    None => Err(AppError::InsufficientPermissions {
+                        your_level: None,
+                        required_level: Permissions::$rights,
+                    }),
+                    Some(AccountStatus { suspension: Some(suspension), .. }) => {
+                        Err(suspension.into())
                     }
-                };
-                let database = match req.extensions().get::<Arc<PgPool>>() {
-                    Some(db) => db,
-                    None => {
-                        tracing::error!("Unable to get database in authorization_guard of [{}].", Permissions::$rights);
-
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(json!({"error": "Unable to establish connection with database."})),
-                        ));
+                    Some(status) if status.disabled => Err(AppError::AccountDisabled),
+                    Some(status) if status.password_reset_required => {
+                        Err(AppError::PasswordResetRequired)
                     }
-                };
-
-            match session_info.read_permissions(database).await {
-                    Ok(None) => {
-                        Err((
-                            StatusCode::FORBIDDEN,
-                            Json(json!({
-                                "your_level": "None",
-                                "required_level": Permissions::$rights
-                            }))
-                        ))
-                    }
-                    Ok(Some(permissions)) => {
-                        if permissions < Permissions::$rights {
-                         Err((
-                            StatusCode::FORBIDDEN,
-                            Json(json!({
-                                "your_level": permissions.to_string(),
-                                "required_level": Permissions::$rights
-                            }))
-                        ))
-
-                        } else {
-                            Ok($struct_name {})
-                        }
-                    }
-                    Err(error) => {
-                        tracing::error!("Error occured while guarding access level. Error = [{}]", error);
-
-                        Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(json!({
-                                "error": error.to_string()
-                            }))
-                        ))
+                    Some(status) if status.permissions < Permissions::$rights => {
+                        Err(AppError::InsufficientPermissions {
+                            your_level: Some(status.permissions),
+                            required_level: Permissions::$rights,
+                        })
                     }
+                    Some(status) => Ok($struct_name {
+                        username: session
+                            .as_ref()
+                            .and_then(session::SessionInfo::username)
+                            .unwrap_or_default()
+                            .to_owned(),
+                        permissions: status.permissions,
+                    }),
                 }
             }
         }
@@ -113,57 +125,21 @@ impl<B> FromRequest<B> for Unauthorized
 where
     B: Send,
 {
-    type Rejection = (StatusCode, Json<Value>);
+    type Rejection = AppError;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let session_info = match req.extract::<session::SessionInfo>().await {
-            Ok(session_info) => session_info,
-            Err(error) => {
-                tracing::warn!(
-                    "Error while retriving session info from request. Error = [{}]",
-                    error.1
-                );
-
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": "Unable to extract session info."})),
-                ));
-            }
-        };
-        let database = match req.extensions().get::<Arc<PgPool>>() {
-            Some(db) => db,
-            None => {
-                tracing::error!("Unable to get database in authorization_guard of unauthorized");
-
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": "Unable to establish connection with database."})),
-                ));
-            }
-        };
-
-        match session_info.read_permissions(database).await {
-            Ok(None) => Ok(Unauthorized {}),
-            Ok(Some(permissions)) => Err((
-                StatusCode::FORBIDDEN,
-                Json(json!({
-                    "your_level": permissions.to_string(),
-                    "required_level": "Unauthorized"
-                })),
-            )),
-            Err(error) => {
-                tracing::error!(
-                    "Error occured while guarding access level. Error = [{}]",
-                    error
-                );
-
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({
-                        "error": error.to_string()
-                    })),
-                ))
-            }
+        let session = req.extract::<session::ExistingSession>().await?.0;
+        let (database, cache) = (database(req)?, cache(req)?);
+
+        match account_status(session.as_ref(), database, cache).await? {
+            None => Ok(Unauthorized {}),
+            Some(AccountStatus {
+                suspension: Some(suspension),
+                ..
+            }) => Err(suspension.into()),
+            Some(status) => Err(AppError::AlreadyLoggedIn {
+                your_level: status.permissions,
+            }),
         }
     }
 }
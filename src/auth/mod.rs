@@ -1,17 +1,21 @@
+mod bootstrap;
 mod credentials;
 mod guards;
 mod service;
 
 use axum::Router;
+pub use bootstrap::{bootstrap_admin, bootstrap_from_env, BootstrapOutcome};
 pub use credentials::Hasher;
 pub use guards::{AdminGuard, ModeratorGuard, Unauthorized, UserGuard};
-pub use service::AuthError;
+pub use service::{insert_user, verify_credentials, AuthError};
 
 use std::{fmt::Display, str::FromStr};
 
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
 pub enum Permissions {
     User,
     Moderator,
@@ -20,10 +24,10 @@ pub enum Permissions {
 
 impl Display for Permissions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let result_string = match &self {
-            &Permissions::User => "User",
-            &Permissions::Admin => "Admin",
-            &Permissions::Moderator => "Moderator",
+        let result_string = match self {
+            Permissions::User => "User",
+            Permissions::Admin => "Admin",
+            Permissions::Moderator => "Moderator",
         };
 
         write!(f, "{result_string}")
@@ -43,16 +47,233 @@ impl FromStr for Permissions {
     }
 }
 
+/// Decides who is allowed to sign up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistrationMode {
+    /// Anyone can sign up. Invite code is optional.
+    #[default]
+    Open,
+    /// Sign up requires valid invite code.
+    InviteOnly,
+    /// Nobody can sign up.
+    Closed,
+}
+
+impl Display for RegistrationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result_string = match self {
+            RegistrationMode::Open => "open",
+            RegistrationMode::InviteOnly => "invite-only",
+            RegistrationMode::Closed => "closed",
+        };
+
+        write!(f, "{result_string}")
+    }
+}
+
+impl FromStr for RegistrationMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(RegistrationMode::Open),
+            "invite-only" => Ok(RegistrationMode::InviteOnly),
+            "closed" => Ok(RegistrationMode::Closed),
+            _ => Err("Given string does not represent registration mode."),
+        }
+    }
+}
+
+/// State of the account which guards take into
+/// consideration while authorizing the request.
+#[derive(Clone)]
+pub struct AccountStatus {
+    pub permissions: Permissions,
+    pub disabled: bool,
+    pub password_reset_required: bool,
+    pub suspension: Option<Suspension>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Suspension {
+    pub until: NaiveDateTime,
+    pub reason: String,
+}
+
+impl Suspension {
+    /// Builds suspension from database columns, returning
+    /// `None` if account is not suspended or suspension has expired.
+    pub fn active(until: Option<NaiveDateTime>, reason: Option<String>) -> Option<Self> {
+        match until {
+            Some(until) if until > chrono::Utc::now().naive_utc() => Some(Suspension {
+                until,
+                reason: reason.unwrap_or_default(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    service::register,
+    service::login,
+    service::logout,
+    service::change_password
+))]
+pub struct ApiDoc;
+
 pub fn routes() -> Router {
-    Router::new().route("/signup", axum::routing::post(service::register))
+    Router::new()
+        .route("/signup", axum::routing::post(service::register))
+        .route("/login", axum::routing::post(service::login))
+        .route("/logout", axum::routing::post(service::logout))
+        .route("/password", axum::routing::post(service::change_password))
+        .route("/activity", axum::routing::get(crate::audit::user_activity))
+        .route("/csrf", axum::routing::get(crate::session::csrf_token))
 }
 
+#[cfg(test)]
 mod tests {
-    use super::Permissions;
+    use serde_json::json;
+
+    use super::{verify_credentials, AuthError, Permissions};
+    use crate::{session::SESSION_COOKIE_NAME, testing::TestApp};
 
     #[test]
     fn permissions_hierarchy() {
         assert!(Permissions::User < Permissions::Moderator);
         assert!(Permissions::Moderator < Permissions::Admin);
     }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn signup_login_and_logout() {
+        let app = TestApp::spawn().await;
+        let mut client = app.client();
+
+        assert_eq!(client.signup("alice", "correct horse").await.status, 201);
+        assert_eq!(
+            client.signup("alice", "battery staple").await.code(),
+            "UsernameTaken"
+        );
+        assert_eq!(
+            client.get("/auth/activity").await.code(),
+            "InsufficientPermissions"
+        );
+
+        assert_eq!(client.login("alice", "correct horse").await.status, 200);
+        assert_eq!(client.get("/auth/activity").await.status, 200);
+        assert_eq!(
+            client.login("alice", "correct horse").await.code(),
+            "AlreadyLoggedIn"
+        );
+
+        assert_eq!(client.logout().await.status, 200);
+        assert_eq!(
+            client.get("/auth/activity").await.code(),
+            "InsufficientPermissions"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn login_replaces_session() {
+        let app = TestApp::spawn().await;
+        app.logged_in("alice", "correct horse").await;
+
+        // Attacker plants own anonymous session in the victim's browser.
+        let mut attacker = app.client();
+        let planted_token = attacker.fetch_csrf_token().await;
+        let planted_session = attacker.session_id().unwrap().to_owned();
+
+        let mut victim = app.client();
+        victim.set_cookie(SESSION_COOKIE_NAME, &planted_session);
+        victim.set_csrf_token(planted_token);
+        assert_eq!(victim.login("alice", "correct horse").await.status, 200);
+
+        assert_ne!(victim.session_id().unwrap(), planted_session);
+        assert_eq!(victim.get("/auth/activity").await.status, 200);
+        assert_eq!(victim.logout().await.status, 200);
+
+        assert_eq!(
+            attacker.get("/auth/activity").await.code(),
+            "InsufficientPermissions"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn invalid_credentials_are_rejected() {
+        let app = TestApp::spawn().await;
+        let mut client = app.client();
+        client.signup("alice", "correct horse").await;
+
+        let wrong_password = client.login("alice", "battery staple").await;
+        assert_eq!(wrong_password.status, 401);
+        assert_eq!(wrong_password.code(), "InvalidCredentials");
+        assert_eq!(
+            client.login("bob", "correct horse").await.code(),
+            "InvalidCredentials"
+        );
+        assert_eq!(
+            client.get("/auth/activity").await.code(),
+            "InsufficientPermissions"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn hashing_changes_keep_stored_passwords_valid() {
+        let app = TestApp::spawn().await;
+        app.client().signup("alice", "correct horse").await;
+
+        let hasher = app.hasher_with(|hashing| hashing.iterations += 1);
+        let verified = verify_credentials(app.database(), &hasher, "alice", "correct horse").await;
+        assert!(verified.is_ok());
+
+        let rejected = verify_credentials(app.database(), &hasher, "alice", "battery staple").await;
+        assert!(matches!(rejected, Err(AuthError::InvalidPassword)));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn changed_password_is_required_at_next_login() {
+        let app = TestApp::spawn().await;
+        let mut client = app.logged_in("alice", "correct horse").await;
+
+        let change = json!({ "old_password": "correct horse", "new_password": "battery staple" });
+        assert_eq!(client.post("/auth/password", change).await.status, 200);
+
+        let mut other = app.client();
+        assert_eq!(
+            other.login("alice", "correct horse").await.code(),
+            "InvalidCredentials"
+        );
+        assert_eq!(other.login("alice", "battery staple").await.status, 200);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn administration_requires_permissions() {
+        let app = TestApp::spawn().await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+        app.logged_in("bob", "battery staple").await;
+
+        let forbidden = alice.get("/admin/users").await;
+        assert_eq!(forbidden.code(), "InsufficientPermissions");
+        assert_eq!(forbidden.body["required_level"], "Admin");
+
+        app.set_permissions("alice", Permissions::Admin).await;
+        assert_eq!(alice.get("/admin/users").await.status, 200);
+
+        let promotion = json!({ "permissions": "Moderator" });
+        let promoted = alice.put("/admin/users/bob/permissions", promotion).await;
+        assert_eq!(promoted.status, 200);
+        assert_eq!(
+            alice.get("/admin/users/bob").await.body["user"]["permissions"],
+            "Moderator"
+        );
+    }
 }
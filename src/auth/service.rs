@@ -1,41 +1,165 @@
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc};
 
-use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use argon2::Params;
+use axum::{http::StatusCode, Extension, Json};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use sqlx::{query, PgPool, Row};
+use serde_json::json;
+use sqlx::{
+    postgres::{PgExecutor, PgRow},
+    query, PgPool, Row,
+};
+use utoipa::ToSchema;
 
-use super::{Hasher, Permissions, Unauthorized};
+use super::{
+    credentials::{self, HashedPassword, PasswordHash, HASH_LENGTH},
+    Hasher, Permissions, RegistrationMode, Suspension, Unauthorized,
+};
+use crate::{
+    audit::{AuditLog, EventType},
+    config::Config,
+    database::Database,
+    error::{ApiResult, AppError, Problem},
+    extract::ValidJson,
+    invites,
+    metrics::Metrics,
+    session::{self, CreatedSession, ExistingSession, SessionCache, SessionInfo},
+};
 
-#[derive(Deserialize)]
+/// Salt of the hash computed for unknown usernames, so that they
+/// take as long to reject as wrong passwords of existing users.
+const UNKNOWN_USER_SALT: &[u8] = b"budgeters-unknown-user";
+
+#[derive(Deserialize, ToSchema)]
 pub struct LoginForm {
     username: String,
     password: String,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct SignupForm {
+    username: String,
+    password: String,
+    /// Required when registration is invite-only.
+    invite_code: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordChangeForm {
+    old_password: String,
+    new_password: String,
+}
+
 #[derive(Serialize)]
 pub enum AuthError {
     DatabaseError(String),
     InvalidUsername,
+    InvalidPassword,
+    AccountDisabled,
+    Suspended {
+        until: NaiveDateTime,
+        reason: String,
+    },
     UsernameTaken,
+    AdminExists,
+    InvalidInvite,
 }
 
-async fn insert_user(
-    database: &PgPool,
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DatabaseError(error) => write!(f, "DatabaseError. {error}"),
+            Self::InvalidUsername => write!(f, "User of given name does not exist."),
+            Self::InvalidPassword => write!(f, "Given password is incorrect."),
+            Self::AccountDisabled => write!(f, "Account has been disabled."),
+            Self::Suspended { until, reason } => {
+                write!(f, "Account is suspended until {until}. Reason: {reason}")
+            }
+            Self::UsernameTaken => write!(f, "Username is already taken."),
+            Self::AdminExists => write!(
+                f,
+                "Administrator account already exists. Use force to proceed anyway."
+            ),
+            Self::InvalidInvite => {
+                write!(f, "Invite code is invalid, expired or has been used up.")
+            }
+        }
+    }
+}
+
+impl AuthError {
+    fn name(&self) -> &'static str {
+        match self {
+            AuthError::DatabaseError(_) => "DatabaseError",
+            AuthError::InvalidUsername => "InvalidUsername",
+            AuthError::InvalidPassword => "InvalidPassword",
+            AuthError::AccountDisabled => "AccountDisabled",
+            AuthError::Suspended { .. } => "Suspended",
+            AuthError::UsernameTaken => "UsernameTaken",
+            AuthError::AdminExists => "AdminExists",
+            AuthError::InvalidInvite => "InvalidInvite",
+        }
+    }
+}
+
+/// Row of `auth_info` needed to verify user's credentials.
+struct StoredCredentials {
+    salt: Vec<u8>,
+    password_hash: Vec<u8>,
+    /// `None` when stored parameters are not valid Argon2 ones.
+    params: Option<Params>,
+    disabled: bool,
+    password_reset_required: bool,
+    suspension: Option<Suspension>,
+}
+
+impl StoredCredentials {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(StoredCredentials {
+            salt: row.try_get("salt")?,
+            password_hash: row.try_get("password_hash")?,
+            params: credentials::stored_params(
+                row.try_get("memory_blocks")?,
+                row.try_get("iterations")?,
+                row.try_get("parallelism")?,
+            ),
+            disabled: row.try_get("disabled")?,
+            password_reset_required: row.try_get("password_reset_required")?,
+            suspension: Suspension::active(
+                row.try_get("suspended_until")?,
+                row.try_get("suspension_reason")?,
+            ),
+        })
+    }
+}
+
+/// Outcome of successful credentials verification.
+pub struct VerifiedUser {
+    pub password_reset_required: bool,
+}
+
+pub async fn insert_user<'e, E>(
+    executor: E,
     username: &str,
-    password_hash: &[u8],
-    salt: &[u8],
+    password: &HashedPassword,
     permissions: Permissions,
-) -> Result<(), AuthError> {
+) -> Result<(), AuthError>
+where
+    E: PgExecutor<'e>,
+{
     let insert_stmt = include_str!("../../postgres/auth/register_user.sql");
+    let (memory_blocks, iterations, parallelism) = password.stored_params();
 
-    let query_prepared = query(&insert_stmt)
+    let query_prepared = query(insert_stmt)
         .bind(username)
-        .bind(salt)
-        .bind(password_hash)
-        .bind(permissions.to_string());
+        .bind(password.salt.as_bytes())
+        .bind(password.hash.as_slice())
+        .bind(permissions.to_string())
+        .bind(memory_blocks)
+        .bind(iterations)
+        .bind(parallelism);
 
-    match query_prepared.execute(database).await {
+    match query_prepared.execute(executor).await {
         Ok(_) => Ok(()),
         Err(e) => Err(e.as_database_error().map_or_else(
             || AuthError::DatabaseError(e.to_string()),
@@ -55,46 +179,345 @@ async fn insert_user(
     }
 }
 
+/// Creates account in single transaction with claiming the invite,
+/// if one was given. Returns id of the claimed invite.
+async fn create_account(
+    database: &PgPool,
+    username: &str,
+    password: &HashedPassword,
+    invite_code: Option<&str>,
+) -> Result<Option<i64>, AuthError> {
+    let database_error = |e: sqlx::Error| AuthError::DatabaseError(e.to_string());
+    let mut transaction = database.begin().await.map_err(database_error)?;
+
+    let invite = match invite_code {
+        Some(code) => match invites::claim_invite(code, &mut transaction)
+            .await
+            .map_err(database_error)?
+        {
+            Some(invite) => Some(invite),
+            None => return Err(AuthError::InvalidInvite),
+        },
+        None => None,
+    };
+
+    let permissions = invite
+        .as_ref()
+        .and_then(|invite| invite.permissions)
+        .unwrap_or(Permissions::User);
+
+    insert_user(&mut transaction, username, password, permissions).await?;
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(invite.map(|invite| invite.invite_id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/signup",
+    tag = "auth",
+    request_body = SignupForm,
+    responses(
+        (status = 201, description = "Account has been created.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 409, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
 pub async fn register(
-    signup_form: Json<LoginForm>,
-    database: Extension<Arc<PgPool>>,
-    hasher: Extension<Arc<Hasher<'_>>>,
+    signup_form: ValidJson<SignupForm>,
+    database: Extension<Arc<Database>>,
+    hasher: Extension<Arc<Hasher>>,
+    config: Extension<Arc<Config>>,
+    metrics: Extension<Arc<Metrics>>,
+    audit: AuditLog,
     _guard: Unauthorized,
-) -> (StatusCode, Json<Value>) {
-    let (password_hash, user_salt) = hasher.process_password(signup_form.password.as_bytes());
-
-    match insert_user(
-        database.as_ref(),
-        &signup_form.username,
-        &password_hash,
-        user_salt.as_bytes(),
-        Permissions::User,
-    )
-    .await
-    {
-        Ok(()) => (
+) -> ApiResult {
+    let outcome = async {
+        let invite_code = signup_form.invite_code.as_deref();
+
+        match (config.accounts.registration_mode, invite_code) {
+            (RegistrationMode::Closed, _) => return Err(AppError::RegistrationClosed),
+            (RegistrationMode::InviteOnly, None) => return Err(AppError::InviteRequired),
+            _ => {}
+        }
+
+        let password = hasher
+            .process_password(signup_form.password.as_bytes())
+            .await;
+
+        let invite_id = create_account(
+            database.writer(),
+            &signup_form.username,
+            &password,
+            invite_code,
+        )
+        .await?;
+
+        let event = audit.event(EventType::Signup).actor(&signup_form.username);
+        let event = match invite_id {
+            Some(invite_id) => event.details(format!("invite_id = {invite_id}")),
+            None => event,
+        };
+        audit.record(event).await;
+
+        Ok((
             StatusCode::CREATED,
             Json(json!({
                 "error": "None"
             })),
-        ),
-        Err(AuthError::UsernameTaken) => (
-            StatusCode::CONFLICT,
+        ))
+    }
+    .await;
+
+    metrics.record_signup(&outcome);
+    outcome
+}
+
+pub async fn verify_credentials(
+    database: &PgPool,
+    hasher: &Hasher,
+    username: &str,
+    password: &str,
+) -> Result<VerifiedUser, AuthError> {
+    let read_stmt = include_str!("../../postgres/auth/read_credentials.sql");
+
+    let row = match query(read_stmt)
+        .bind(username)
+        .fetch_optional(database)
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            hasher
+                .password_check(
+                    password.as_bytes(),
+                    UNKNOWN_USER_SALT,
+                    &[0; HASH_LENGTH],
+                    hasher.params(),
+                )
+                .await;
+            return Err(AuthError::InvalidUsername);
+        }
+        Err(e) => return Err(AuthError::DatabaseError(e.to_string())),
+    };
+
+    let stored =
+        StoredCredentials::from_row(&row).map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+    let stored_hash: PasswordHash = stored.password_hash.try_into().map_err(|_| {
+        AuthError::DatabaseError(format!(
+            "Stored password hash of user [{username}] has invalid length."
+        ))
+    })?;
+    let params = stored.params.ok_or_else(|| {
+        AuthError::DatabaseError(format!(
+            "Stored hashing parameters of user [{username}] are invalid."
+        ))
+    })?;
+
+    if !hasher
+        .password_check(password.as_bytes(), &stored.salt, &stored_hash, &params)
+        .await
+    {
+        return Err(AuthError::InvalidPassword);
+    }
+
+    if stored.disabled {
+        return Err(AuthError::AccountDisabled);
+    }
+
+    if let Some(Suspension { until, reason }) = stored.suspension {
+        return Err(AuthError::Suspended { until, reason });
+    }
+
+    Ok(VerifiedUser {
+        password_reset_required: stored.password_reset_required,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginForm,
+    responses(
+        (status = 200, description = "User has been given a new session, whose cookie \
+            replaces the previous one. Its CSRF token is returned as well.", body = Object,
+            example = json!({ "error": "None", "password_reset_required": false, "csrf_token": "3q2-7wYb..." })),
+        (status = 401, response = Problem),
+        (status = 403, response = Problem),
+        (status = 429, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+// Every extractor is needed by the handler.
+#[allow(clippy::too_many_arguments)]
+pub async fn login(
+    login_form: ValidJson<LoginForm>,
+    session_info: SessionInfo,
+    Extension(created_session): Extension<CreatedSession>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    hasher: Extension<Arc<Hasher>>,
+    config: Extension<Arc<Config>>,
+    metrics: Extension<Arc<Metrics>>,
+    audit: AuditLog,
+    _guard: Unauthorized,
+) -> ApiResult {
+    let outcome = async {
+        let verified = match verify_credentials(
+            database.writer(),
+            hasher.as_ref(),
+            &login_form.username,
+            &login_form.password,
+        )
+        .await
+        {
+            Ok(verified) => verified,
+            Err(error) => {
+                audit
+                    .record(
+                        audit
+                            .event(EventType::LoginFailed)
+                            .target(&login_form.username)
+                            .details(error.name()),
+                    )
+                    .await;
+
+                return Err(error.into());
+            }
+        };
+
+        let renewed = session::renew_session(
+            &session_info,
+            &created_session,
+            database.writer(),
+            &cache,
+            &login_form.username,
+            config.session.lifetime(),
+        )
+        .await?;
+
+        audit
+            .record(
+                audit
+                    .event(EventType::LoginSucceeded)
+                    .actor(&login_form.username),
+            )
+            .await;
+
+        Ok((
+            StatusCode::OK,
             Json(json!({
-                "error": "UsernameTaken"
+                "error": "None",
+                "password_reset_required": verified.password_reset_required,
+                "csrf_token": renewed.csrf_token()
             })),
-        ),
-        Err(AuthError::DatabaseError(error)) => {
-            tracing::error!("Error occured while signup process. Error = [{}]", error);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": "DatabaseError"
-                })),
-            )
-        }
-        _ => {
-            unimplemented!("Other error are not possible.");
-        }
+        ))
     }
+    .await;
+
+    metrics.record_login(&outcome);
+    outcome
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session has been removed.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn logout(
+    ExistingSession(session_info): ExistingSession,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+) -> ApiResult {
+    let session_info = session_info.ok_or(AppError::NotLoggedIn)?;
+    let username = session_info.username().ok_or(AppError::NotLoggedIn)?;
+
+    session::remove_session(session_info.session_id(), database.writer()).await?;
+    cache.invalidate_user(username, database.writer()).await?;
+
+    audit
+        .record(audit.event(EventType::Logout).actor(username))
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password",
+    tag = "auth",
+    request_body = PasswordChangeForm,
+    responses(
+        (status = 200, description = "Password has been changed.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 401, response = Problem),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+/// Changes password of the logged in user. It does not use guards,
+/// because users with forced password reset must be able to reach it.
+pub async fn change_password(
+    password_form: ValidJson<PasswordChangeForm>,
+    ExistingSession(session_info): ExistingSession,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    hasher: Extension<Arc<Hasher>>,
+    audit: AuditLog,
+) -> ApiResult {
+    let session_info = session_info.ok_or(AppError::NotLoggedIn)?;
+    let username = session_info.username().ok_or(AppError::NotLoggedIn)?;
+
+    verify_credentials(
+        database.writer(),
+        hasher.as_ref(),
+        username,
+        &password_form.old_password,
+    )
+    .await?;
+
+    let password = hasher
+        .process_password(password_form.new_password.as_bytes())
+        .await;
+    let (memory_blocks, iterations, parallelism) = password.stored_params();
+    let update_stmt = include_str!("../../postgres/auth/change_password.sql");
+
+    query(update_stmt)
+        .bind(username)
+        .bind(password.salt.as_bytes())
+        .bind(password.hash.as_slice())
+        .bind(memory_blocks)
+        .bind(iterations)
+        .bind(parallelism)
+        .execute(database.writer())
+        .await?;
+    // Clears forced password reset, which guards would keep seeing otherwise.
+    cache.invalidate_user(username, database.writer()).await?;
+
+    audit
+        .record(audit.event(EventType::PasswordChanged).actor(username))
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
 }
@@ -0,0 +1,192 @@
+mod diagnostics;
+mod sessions;
+mod users;
+
+use std::{io::BufRead, path::PathBuf};
+
+use clap::{ArgGroup, Parser, Subcommand};
+use sqlx::PgPool;
+
+use crate::{
+    auth::{self, Hasher, Permissions},
+    migrations,
+};
+
+pub use diagnostics::diagnose;
+
+const ADMIN_PASSWORD_VARIABLE: &str = "BG_ADMIN_PASSWORD";
+
+#[derive(Parser)]
+#[command(version, about = "Budgeters application server.")]
+pub struct Cli {
+    /// Path to TOML config file. Defaults to BG_CONFIG env variable or budgeters.toml.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manages administrator accounts.
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Manages user accounts.
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+    /// Lists, revokes and purges sessions.
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Applies pending database migrations.
+    Migrate {
+        /// Only list pending migrations without applying them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Prints effective configuration with secrets hidden, then checks
+    /// that the database, its replica and TLS certificate can be used.
+    Diagnose,
+}
+
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// Creates administrator account or promotes existing user to administrator.
+    /// Disabled accounts and ones marked as deleted are not promoted.
+    /// Password is read from BG_ADMIN_PASSWORD env variable or from standard input.
+    Create {
+        username: String,
+        /// Proceed even if an administrator already exists.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Creates account regardless of the registration mode.
+    /// Password is read from BG_ADMIN_PASSWORD env variable or from standard input.
+    Create {
+        username: String,
+        /// User, Moderator or Admin.
+        #[arg(long, default_value = "User")]
+        permissions: Permissions,
+    },
+    /// Sets new password and revokes every session of the user.
+    /// Password is read from BG_ADMIN_PASSWORD env variable or from standard input.
+    ResetPassword {
+        username: String,
+        /// Makes the user choose another password after logging in.
+        #[arg(long)]
+        require_change: bool,
+    },
+    /// Changes permissions of the user.
+    SetPermissions {
+        username: String,
+        /// User, Moderator or Admin.
+        permissions: Permissions,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// Lists unexpired sessions. They are identified by
+    /// the same fingerprint which is used in logs.
+    List {
+        /// Only list sessions of given user.
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Revokes single session or every session of the user.
+    #[command(group(ArgGroup::new("target").required(true).args(["session", "user"])))]
+    Revoke {
+        /// Fingerprint of the session, as printed by `session list`.
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Removes expired sessions.
+    Purge,
+}
+
+fn read_password(prompt: &str) -> Result<String, String> {
+    if let Ok(password) = std::env::var(ADMIN_PASSWORD_VARIABLE) {
+        return Ok(password);
+    }
+
+    eprintln!("{prompt}");
+
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .map_err(|e| format!("Unable to read password from standard input. {e}"))?;
+
+    match password.trim_end_matches(['\r', '\n']) {
+        "" => Err("Password cannot be empty.".into()),
+        password => Ok(password.to_owned()),
+    }
+}
+
+/// Executes given command and returns process exit code.
+pub async fn run(command: Command, database: &PgPool, hasher: &Hasher) -> i32 {
+    match command {
+        Command::Admin {
+            command: AdminCommand::Create { username, force },
+        } => {
+            let password = match read_password("Password for the administrator account:") {
+                Ok(password) => password,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return 1;
+                }
+            };
+
+            match auth::bootstrap_admin(database, hasher, &username, &password, force, true).await {
+                Ok(auth::BootstrapOutcome::Created) => {
+                    println!("Created administrator account [{username}].");
+                    0
+                }
+                Ok(auth::BootstrapOutcome::Promoted) => {
+                    println!("Promoted existing user [{username}] to administrator. Password was left unchanged.");
+                    0
+                }
+                Err(error) => {
+                    eprintln!("Unable to create administrator. {error}");
+                    1
+                }
+            }
+        }
+        Command::User { command } => users::run(command, database, hasher).await,
+        Command::Session { command } => sessions::run(command, database).await,
+        Command::Migrate { dry_run } => match migrations::migrate(database, dry_run).await {
+            Ok(migrations) if migrations.is_empty() => {
+                println!("Database schema is up to date.");
+                0
+            }
+            Ok(migrations) => {
+                let verb = if dry_run { "Pending" } else { "Applied" };
+
+                for migration in migrations {
+                    println!("{verb} migration {migration}.");
+                }
+
+                0
+            }
+            Err(error) => {
+                eprintln!("Unable to migrate database. {error}");
+                1
+            }
+        },
+        Command::Diagnose => {
+            unreachable!("Diagnostics should run before connecting to the database.")
+        }
+    }
+}
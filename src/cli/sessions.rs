@@ -0,0 +1,151 @@
+use chrono::NaiveDateTime;
+use sqlx::{query, FromRow, PgPool};
+
+use super::SessionCommand;
+use crate::{
+    audit::{AuditEvent, EventType},
+    logging, session,
+};
+
+#[derive(FromRow)]
+struct ListedSession {
+    session_id: String,
+    username: Option<String>,
+    client_ip: Option<String>,
+    expiration_date: NaiveDateTime,
+}
+
+pub async fn run(command: SessionCommand, database: &PgPool) -> i32 {
+    let outcome = match command {
+        SessionCommand::List { user } => list(database, user.as_deref()).await,
+        SessionCommand::Revoke {
+            session: Some(fingerprint),
+            ..
+        } => revoke_session(database, &fingerprint).await,
+        SessionCommand::Revoke {
+            user: Some(username),
+            ..
+        } => revoke_user_sessions(database, &username).await,
+        SessionCommand::Revoke { .. } => {
+            unreachable!("Clap requires either session or user to be given.")
+        }
+        SessionCommand::Purge => purge(database).await,
+    };
+
+    match outcome {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("Unable to access sessions. {error}");
+            1
+        }
+    }
+}
+
+async fn read_sessions(
+    database: &PgPool,
+    username: Option<&str>,
+) -> Result<Vec<ListedSession>, sqlx::Error> {
+    let list_stmt = include_str!("../../postgres/session/list_sessions.sql");
+
+    sqlx::query_as(list_stmt)
+        .bind(username)
+        .bind(chrono::Utc::now().naive_utc())
+        .fetch_all(database)
+        .await
+}
+
+/// Sessions are never printed by their id, since anyone able to
+/// read it could take them over. Logs use the same fingerprint.
+async fn list(database: &PgPool, username: Option<&str>) -> Result<i32, sqlx::Error> {
+    let sessions = read_sessions(database, username).await?;
+
+    if sessions.is_empty() {
+        println!("There are no active sessions.");
+        return Ok(0);
+    }
+
+    println!(
+        "{:<16}  {:<24}  {:<39}  EXPIRES",
+        "SESSION", "USER", "CLIENT"
+    );
+
+    for session in sessions {
+        println!(
+            "{:<16}  {:<24}  {:<39}  {}",
+            logging::redact(&session.session_id),
+            session.username.as_deref().unwrap_or("-"),
+            session.client_ip.as_deref().unwrap_or("-"),
+            session.expiration_date.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    Ok(0)
+}
+
+async fn revoke_session(database: &PgPool, fingerprint: &str) -> Result<i32, sqlx::Error> {
+    let session = read_sessions(database, None)
+        .await?
+        .into_iter()
+        .find(|session| logging::redact(&session.session_id) == fingerprint);
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            eprintln!("There is no active session [{fingerprint}].");
+            return Ok(1);
+        }
+    };
+
+    let mut transaction = database.begin().await?;
+
+    let remove_stmt = include_str!("../../postgres/session/remove_session.sql");
+    query(remove_stmt)
+        .bind(&session.session_id)
+        .execute(&mut transaction)
+        .await?;
+
+    if let Some(username) = &session.username {
+        AuditEvent::new(EventType::SessionsRevoked)
+            .target(username)
+            .details(format!("session = {fingerprint}"))
+            .record(&mut transaction)
+            .await?;
+        session::notify_invalidation(username, &mut transaction).await?;
+    }
+
+    transaction.commit().await?;
+    println!("Revoked session [{fingerprint}].");
+
+    Ok(0)
+}
+
+async fn revoke_user_sessions(database: &PgPool, username: &str) -> Result<i32, sqlx::Error> {
+    let mut transaction = database.begin().await?;
+
+    let revoked = session::remove_user_sessions(username, &mut transaction).await?;
+    AuditEvent::new(EventType::SessionsRevoked)
+        .target(username)
+        .details(format!("count = {revoked}"))
+        .record(&mut transaction)
+        .await?;
+    session::notify_invalidation(username, &mut transaction).await?;
+
+    transaction.commit().await?;
+    println!("Revoked {revoked} session(s) of [{username}].");
+
+    Ok(0)
+}
+
+async fn purge(database: &PgPool) -> Result<i32, sqlx::Error> {
+    let purge_stmt = include_str!("../../postgres/session/purge_expired.sql");
+
+    let purged = query(purge_stmt)
+        .bind(chrono::Utc::now().naive_utc())
+        .execute(database)
+        .await?
+        .rows_affected();
+
+    println!("Purged {purged} expired session(s).");
+
+    Ok(0)
+}
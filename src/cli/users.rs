@@ -0,0 +1,263 @@
+use sqlx::{query, PgPool};
+
+use super::{read_password, UserCommand};
+use crate::{
+    audit::{AuditEvent, EventType},
+    auth::{self, AuthError, Hasher, Permissions},
+    session,
+};
+
+/// Outcome of a command modifying existing account.
+enum Modification {
+    Done,
+    UserNotFound,
+}
+
+pub async fn run(command: UserCommand, database: &PgPool, hasher: &Hasher) -> i32 {
+    let (username, outcome) = match command {
+        UserCommand::Create {
+            username,
+            permissions,
+        } => {
+            let password = match read_password("Password for the new account:") {
+                Ok(password) => password,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return 1;
+                }
+            };
+
+            return match create(database, hasher, &username, &password, permissions).await {
+                Ok(()) => {
+                    println!("Created account [{username}] with {permissions} permissions.");
+                    0
+                }
+                Err(error) => {
+                    eprintln!("Unable to create account. {error}");
+                    1
+                }
+            };
+        }
+        UserCommand::ResetPassword {
+            username,
+            require_change,
+        } => {
+            let password = match read_password("New password:") {
+                Ok(password) => password,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return 1;
+                }
+            };
+
+            let outcome =
+                reset_password(database, hasher, &username, &password, require_change).await;
+            (
+                username,
+                outcome.map(|modification| (modification, "Password has been reset")),
+            )
+        }
+        UserCommand::SetPermissions {
+            username,
+            permissions,
+        } => {
+            let outcome = set_permissions(database, &username, permissions).await;
+            (
+                username,
+                outcome.map(|modification| (modification, "Permissions have been changed")),
+            )
+        }
+    };
+
+    match outcome {
+        Ok((Modification::Done, message)) => {
+            println!("{message} for [{username}].");
+            0
+        }
+        Ok((Modification::UserNotFound, _)) => {
+            eprintln!("User [{username}] does not exist.");
+            1
+        }
+        Err(error) => {
+            eprintln!("Unable to modify account [{username}]. {error}");
+            1
+        }
+    }
+}
+
+/// Creates account regardless of the registration mode.
+async fn create(
+    database: &PgPool,
+    hasher: &Hasher,
+    username: &str,
+    password: &str,
+    permissions: Permissions,
+) -> Result<(), AuthError> {
+    let database_error = |e: sqlx::Error| AuthError::DatabaseError(e.to_string());
+    let mut transaction = database.begin().await.map_err(database_error)?;
+
+    let password = hasher.process_password(password.as_bytes()).await;
+    auth::insert_user(&mut transaction, username, &password, permissions).await?;
+
+    AuditEvent::new(EventType::AccountCreated)
+        .target(username)
+        .details(permissions.to_string())
+        .record(&mut transaction)
+        .await
+        .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)
+}
+
+/// Sets new password and logs the user out everywhere,
+/// since the old one may have been compromised.
+async fn reset_password(
+    database: &PgPool,
+    hasher: &Hasher,
+    username: &str,
+    password: &str,
+    require_change: bool,
+) -> Result<Modification, sqlx::Error> {
+    let mut transaction = database.begin().await?;
+
+    let password = hasher.process_password(password.as_bytes()).await;
+    let (memory_blocks, iterations, parallelism) = password.stored_params();
+    let change_stmt = include_str!("../../postgres/auth/change_password.sql");
+    let changed = query(change_stmt)
+        .bind(username)
+        .bind(password.salt.as_bytes())
+        .bind(password.hash.as_slice())
+        .bind(memory_blocks)
+        .bind(iterations)
+        .bind(parallelism)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if changed == 0 {
+        transaction.rollback().await?;
+        return Ok(Modification::UserNotFound);
+    }
+
+    AuditEvent::new(EventType::PasswordChanged)
+        .target(username)
+        .record(&mut transaction)
+        .await?;
+
+    if require_change {
+        let reset_stmt = include_str!("../../postgres/admin/require_password_reset.sql");
+        query(reset_stmt)
+            .bind(username)
+            .execute(&mut transaction)
+            .await?;
+
+        AuditEvent::new(EventType::PasswordResetForced)
+            .target(username)
+            .record(&mut transaction)
+            .await?;
+    }
+
+    let revoked = session::remove_user_sessions(username, &mut transaction).await?;
+    AuditEvent::new(EventType::SessionsRevoked)
+        .target(username)
+        .details(format!("count = {revoked}"))
+        .record(&mut transaction)
+        .await?;
+    session::notify_invalidation(username, &mut transaction).await?;
+
+    transaction.commit().await?;
+
+    Ok(Modification::Done)
+}
+
+async fn set_permissions(
+    database: &PgPool,
+    username: &str,
+    permissions: Permissions,
+) -> Result<Modification, sqlx::Error> {
+    let mut transaction = database.begin().await?;
+
+    let update_stmt = include_str!("../../postgres/admin/update_permissions.sql");
+    let updated = query(update_stmt)
+        .bind(username)
+        .bind(permissions.to_string())
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if updated == 0 {
+        transaction.rollback().await?;
+        return Ok(Modification::UserNotFound);
+    }
+
+    AuditEvent::new(EventType::PermissionsChanged)
+        .target(username)
+        .details(permissions.to_string())
+        .record(&mut transaction)
+        .await?;
+    session::notify_invalidation(username, &mut transaction).await?;
+
+    transaction.commit().await?;
+
+    Ok(Modification::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn reset_password_logs_user_out() {
+        let app = TestApp::spawn().await;
+        let hasher = app.hasher();
+
+        let created = create(
+            app.database(),
+            &hasher,
+            "alice",
+            "correct horse",
+            Permissions::Moderator,
+        )
+        .await;
+        assert!(created.is_ok());
+        assert!(matches!(
+            create(app.database(), &hasher, "alice", "again", Permissions::User).await,
+            Err(AuthError::UsernameTaken)
+        ));
+
+        let mut client = app.client();
+        assert_eq!(client.login("alice", "correct horse").await.status, 200);
+        assert_eq!(client.get("/me/export").await.status, 200);
+
+        let outcome = reset_password(app.database(), &hasher, "alice", "battery staple", false)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, Modification::Done));
+
+        // The application learns about it asynchronously, through NOTIFY.
+        for attempt in 0.. {
+            if client.get("/me/export").await.status != 200 {
+                break;
+            }
+
+            assert!(attempt < 100, "Cached session survived password reset.");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(
+            app.client().login("alice", "correct horse").await.code(),
+            "InvalidCredentials"
+        );
+        assert_eq!(
+            app.client().login("alice", "battery staple").await.status,
+            200
+        );
+
+        let outcome = set_permissions(app.database(), "bob", Permissions::Admin)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, Modification::UserNotFound));
+    }
+}
@@ -1,31 +1,398 @@
-use sqlx::postgres::{PgConnectOptions, PgPool};
-
-fn read_variable(var_name: &str) -> String {
-    std::env::var(var_name).unwrap_or_else(|_| panic!("Unable to find {} env variable!", var_name))
-}
-
-pub async fn initialize_database_pool() -> PgPool {
-    let database_host = read_variable("BG_HOST");
-    let database_port = read_variable("BG_PORT");
-    let database_user = read_variable("BG_USER");
-    let database_password = read_variable("BG_PASSWORD");
-    let database_name = read_variable("BG_DATABASE");
-
-    let connect_options = PgConnectOptions::new()
-        .database(&database_name)
-        .host(&database_host)
-        .username(&database_user)
-        .password(&database_password)
-        .port(
-            database_port
-                .parse()
-                .expect("Unable to parse BG_PORT env variable."),
-        )
-        .ssl_mode(sqlx::postgres::PgSslMode::Prefer);
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use sqlx::{
+    postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions},
+    Connection, Executor,
+};
+
+use crate::config::DatabaseConfig;
+
+/// How long reads stay on the primary after the replica failed.
+const REPLICA_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// Replica is given up on quickly, since the primary can serve its reads.
+const REPLICA_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Bounds of the delay between attempts to reach the database at startup.
+const STARTUP_RETRY_DELAYS: (Duration, Duration) =
+    (Duration::from_millis(250), Duration::from_secs(10));
+/// SQLSTATE of a server which is starting up or shutting down.
+const CANNOT_CONNECT_NOW: &str = "57P03";
+
+fn connect_options(config: &DatabaseConfig) -> PgConnectOptions {
+    let mut options = match &config.url {
+        Some(url) => url
+            .parse()
+            .expect("Database url should be validated while loading config."),
+        None => PgConnectOptions::new()
+            .database(&config.name)
+            .host(&config.host)
+            .username(&config.user)
+            .password(&config.password)
+            .port(config.port),
+    };
 
-    
+    if let Some(ssl_mode) = config.ssl_mode {
+        options = options.ssl_mode(ssl_mode.into());
+    }
+
+    if let Some(path) = &config.ssl_root_cert {
+        options = options.ssl_root_cert(path);
+    }
+
+    if config.statement_timeout_seconds > 0 {
+        let timeout = format!("{}s", config.statement_timeout_seconds);
+        options = options.options([("statement_timeout", timeout)]);
+    }
+
+    options
+}
 
-    PgPool::connect_with(connect_options)
+fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout())
+        .idle_timeout(config.idle_timeout())
+}
+
+/// Connects once, failing as soon as the database cannot be reached.
+pub async fn connect(config: &DatabaseConfig) -> Result<PgPool, sqlx::Error> {
+    pool_options(config)
+        .connect_with(connect_options(config))
         .await
-        .expect("Unable to establish connection with PostgreSQL database.")
+}
+
+/// Failures which go away once the database finishes starting up.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(error) => error.code().as_deref() == Some(CANNOT_CONNECT_NOW),
+        error => is_connection_error(error),
+    }
+}
+
+/// Waits until the database accepts connections, retrying with exponential
+/// backoff for up to `startup_wait_seconds`. Errors which retrying cannot
+/// fix, like invalid credentials, are returned right away.
+async fn wait_for_database(config: &DatabaseConfig) -> Result<(), sqlx::Error> {
+    let options = connect_options(config);
+    let deadline = Instant::now() + config.startup_wait();
+    let (mut delay, max_delay) = STARTUP_RETRY_DELAYS;
+
+    loop {
+        // Unanswered connection attempts would otherwise hang for minutes.
+        let attempt = tokio::time::timeout(
+            config.acquire_timeout(),
+            PgConnection::connect_with(&options),
+        )
+        .await
+        .unwrap_or(Err(sqlx::Error::PoolTimedOut));
+
+        match attempt {
+            Ok(connection) => return connection.close().await,
+            Err(error) if is_transient(&error) && Instant::now() < deadline => {
+                let pause = delay.min(deadline.saturating_duration_since(Instant::now()));
+                tracing::warn!(
+                    "Database is not available yet, retrying in {:?}. Error = [{}]",
+                    pause,
+                    error
+                );
+
+                tokio::time::sleep(pause).await;
+                delay = (delay * 2).min(max_delay);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Primary database and its optional read replica.
+pub async fn initialize_database(config: &DatabaseConfig) -> Result<Database, sqlx::Error> {
+    wait_for_database(config).await?;
+    let primary = connect(config).await?;
+
+    // Replica connects lazily, so that it cannot prevent startup.
+    let replica = config.replica().map(|replica| {
+        pool_options(&replica)
+            .acquire_timeout(REPLICA_ACQUIRE_TIMEOUT.min(replica.acquire_timeout()))
+            .connect_lazy_with(connect_options(&replica))
+    });
+
+    Ok(Database::new(primary, replica))
+}
+
+/// Errors after which the query may succeed on another server.
+fn is_connection_error(error: &sqlx::Error) -> bool {
+    matches!(
+        error,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
+/// Primary database, which takes every write, and optional read replica.
+/// Replica lags behind the primary, so reads which must see writes of
+/// the same request have to go to the `writer` as well.
+pub struct Database {
+    primary: PgPool,
+    replica: Option<PgPool>,
+    /// Set when the replica failed, reads go to the primary until then.
+    replica_unavailable_until: Mutex<Option<Instant>>,
+}
+
+impl Database {
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        Database {
+            primary,
+            replica,
+            replica_unavailable_until: Mutex::new(None),
+        }
+    }
+
+    pub fn writer(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// Replica, unless there is none or it has failed recently.
+    pub fn reader(&self) -> &PgPool {
+        match &self.replica {
+            Some(replica) if self.replica_available() => replica,
+            _ => &self.primary,
+        }
+    }
+
+    pub fn has_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    fn replica_available(&self) -> bool {
+        let mut unavailable_until = self.replica_unavailable_until.lock().unwrap();
+
+        match *unavailable_until {
+            Some(until) if until > Instant::now() => false,
+            Some(_) => {
+                *unavailable_until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Runs read-only query on the `reader`. When the replica cannot be
+    /// reached, the query is repeated on the primary, which then serves
+    /// reads for a while before the replica is tried again.
+    pub async fn read<'a, T, F, R>(&'a self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: Fn(&'a PgPool) -> R,
+        R: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let reader = self.reader();
+
+        match query(reader).await {
+            Err(error) if is_connection_error(&error) && !std::ptr::eq(reader, &self.primary) => {
+                tracing::warn!(
+                    "Read replica is unavailable, reading from primary. Error = [{}]",
+                    error
+                );
+                *self.replica_unavailable_until.lock().unwrap() =
+                    Some(Instant::now() + REPLICA_RETRY_INTERVAL);
+
+                query(&self.primary).await
+            }
+            outcome => outcome,
+        }
+    }
+
+    pub async fn close(&self) {
+        match &self.replica {
+            Some(replica) => tokio::join!(self.primary.close(), replica.close()).0,
+            None => self.primary.close().await,
+        }
+    }
+}
+
+macro_rules! statements {
+    ($($path:literal),* $(,)?) => {
+        &[$(($path, include_str!(concat!("../postgres/", $path, ".sql")))),*]
+    };
+}
+
+/// Every statement used by the application, except migrations
+/// which are verified by the migrator itself.
+const STATEMENTS: &[(&str, &str)] = statements![
+    "account/delete_account",
+    "account/list_purgeable",
+    "account/mark_deleted",
+    "account/purge_deleted",
+    "account/read_profile",
+    "account/restore_account",
+    "admin/count_users",
+    "admin/delete_user",
+    "admin/list_users",
+    "admin/lock_user",
+    "admin/read_user",
+    "admin/read_user_sessions",
+    "admin/require_password_reset",
+    "admin/set_disabled",
+    "admin/update_permissions",
+    "audit/all_user_events",
+    "audit/count_events",
+    "audit/count_user_events",
+    "audit/insert_event",
+    "audit/list_events",
+    "audit/list_user_events",
+    "auth/admin_exists",
+    "auth/change_password",
+    "auth/check_permissions",
+    "auth/lock_bootstrap",
+    "auth/promote_admin",
+    "auth/read_credentials",
+    "auth/register_user",
+    "invites/claim_invite",
+    "invites/count_invites",
+    "invites/insert_invite",
+    "invites/list_invites",
+    "invites/revoke_invite",
+    "metrics/count_active_sessions",
+    "moderation/lift_suspension",
+    "moderation/read_target",
+    "moderation/suspend_user",
+    "rate_limit/purge_full",
+    "rate_limit/take_token",
+    "session/insert_session",
+    "session/list_sessions",
+    "session/lock_client_ip",
+    "session/notify_invalidation",
+    "session/purge_expired",
+    "session/read_permissions",
+    "session/read_session",
+    "session/remove_session",
+    "session/remove_user_sessions",
+    "session/renew_session",
+];
+
+/// Prepares every statement against the live schema, so that typos and
+/// missing columns are reported at startup instead of at first use.
+/// Returns description of every statement which cannot be prepared.
+pub async fn verify_statements(database: &PgPool) -> Result<(), Vec<String>> {
+    let mut failures = Vec::new();
+
+    for (path, statement) in STATEMENTS {
+        if let Err(error) = database.prepare(statement).await {
+            failures.push(format!("postgres/{path}.sql: {error}"));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx::{postgres::PgPoolOptions, Row};
+
+    use super::{connect, verify_statements, Database, STATEMENTS};
+    use crate::{config::Config, testing::TestApp};
+
+    fn collect_statements(directory: &Path, prefix: &str, found: &mut Vec<String>) {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_str().unwrap().to_owned();
+
+            if path.is_dir() {
+                collect_statements(&path, &format!("{prefix}{name}/"), found);
+            } else {
+                found.push(format!("{prefix}{name}"));
+            }
+        }
+    }
+
+    #[test]
+    fn every_statement_is_verified() {
+        let mut found = Vec::new();
+        let postgres = Path::new(env!("CARGO_MANIFEST_DIR")).join("postgres");
+
+        for directory in std::fs::read_dir(postgres).unwrap() {
+            let directory = directory.unwrap().path();
+            let name = directory.file_name().unwrap().to_str().unwrap().to_owned();
+
+            if name != "migrations" && name != "migrator" {
+                collect_statements(&directory, &format!("{name}/"), &mut found);
+            }
+        }
+
+        for statement in found {
+            assert!(
+                STATEMENTS.iter().any(|(path, _)| *path == statement),
+                "postgres/{statement}.sql is not listed in database::STATEMENTS."
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn statements_match_schema() {
+        let app = TestApp::spawn().await;
+
+        if let Err(failures) = verify_statements(app.database()).await {
+            panic!("Invalid statements:\n{}", failures.join("\n"));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn unreachable_replica_falls_back_to_primary() {
+        let config = Config::load(None).unwrap();
+        let primary = connect(&config.database).await.unwrap();
+        let replica = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(200))
+            .connect_lazy("postgres://budgeters@127.0.0.1:1/unreachable")
+            .unwrap();
+        let database = Database::new(primary, Some(replica));
+
+        assert!(!std::ptr::eq(database.reader(), database.writer()));
+
+        let answer: i32 = database
+            .read(
+                |reader| async move { sqlx::query("SELECT 42 AS answer").fetch_one(reader).await },
+            )
+            .await
+            .and_then(|row| row.try_get("answer"))
+            .unwrap();
+
+        assert_eq!(answer, 42);
+        assert!(std::ptr::eq(database.reader(), database.writer()));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn long_statements_are_cancelled() {
+        let mut config = Config::load(None).unwrap();
+        config.database.statement_timeout_seconds = 1;
+        let database = connect(&config.database).await.unwrap();
+
+        assert!(sqlx::query("SELECT pg_sleep(0.1)")
+            .execute(&database)
+            .await
+            .is_ok());
+
+        let error = sqlx::query("SELECT pg_sleep(3)")
+            .execute(&database)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.as_database_error().and_then(|error| error.code()),
+            Some("57014".into())
+        );
+    }
 }
@@ -0,0 +1,442 @@
+use std::error::Error;
+
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{
+        header::{ALLOW, CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use utoipa::{ToResponse, ToSchema};
+
+use crate::{
+    auth::{AuthError, Permissions, Suspension},
+    session::SessionError,
+};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Response of handlers which either succeed with JSON body or fail with `AppError`.
+pub type ApiResult = Result<(StatusCode, Json<Value>), AppError>;
+
+/// Body of every error response, as described by RFC 7807.
+#[derive(Serialize, ToSchema, ToResponse)]
+#[response(
+    description = "Request failed, `code` tells why.",
+    content_type = "application/problem+json"
+)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    #[schema(example = "urn:budgeters:error:NotLoggedIn")]
+    kind: String,
+    #[schema(example = "You have to be logged in.")]
+    title: &'static str,
+    #[schema(example = 403)]
+    status: u16,
+    #[schema(example = "NotLoggedIn")]
+    code: &'static str,
+    /// Members specific to the error, e.g. `required_level`.
+    #[serde(flatten)]
+    extensions: Map<String, Value>,
+}
+
+/// Every error which can be returned to the client. It is rendered as
+/// RFC 7807 problem details, whose `code` member never changes, so that
+/// clients can rely on it. Internal errors are only described in logs.
+#[derive(Debug)]
+pub enum AppError {
+    NotLoggedIn,
+    AlreadyLoggedIn {
+        your_level: Permissions,
+    },
+    InsufficientPermissions {
+        your_level: Option<Permissions>,
+        required_level: Permissions,
+    },
+    TargetNotModeratable {
+        your_level: Permissions,
+        target_level: Permissions,
+    },
+    InvalidCredentials,
+    AccountDisabled,
+    Suspended {
+        until: NaiveDateTime,
+        reason: String,
+    },
+    PasswordResetRequired,
+    CannotModifyOwnAccount,
+    UsernameTaken,
+    RegistrationClosed,
+    InviteRequired,
+    InvalidInvite,
+    InvalidInviteSettings {
+        max_uses_limit: u32,
+    },
+    InvalidSuspension {
+        max_duration_hours: u32,
+    },
+    UserNotFound,
+    DeletedAccountNotFound,
+    InviteNotFound,
+    MissingSession,
+    TooManySessions,
+    RateLimited {
+        retry_after_seconds: u64,
+    },
+    InvalidCsrfToken,
+    InvalidMetricsToken,
+    /// Body, query or path of the request cannot be deserialized.
+    InvalidRequest {
+        status: StatusCode,
+        detail: String,
+    },
+    RouteNotFound,
+    MethodNotAllowed,
+    DatabaseError(sqlx::Error),
+    Internal(String),
+}
+
+impl AppError {
+    /// Keeps status chosen by axum, e.g. 415 for missing content type. Detail
+    /// is the rejection with its causes, which name the offending field.
+    fn invalid_request(rejection: impl Error + IntoResponse) -> Self {
+        let mut causes = vec![rejection.to_string()];
+        let mut source = rejection.source();
+
+        while let Some(cause) = source {
+            let cause_text = cause.to_string();
+            if causes.last() != Some(&cause_text) {
+                causes.push(cause_text);
+            }
+            source = cause.source();
+        }
+
+        let detail = causes.join(": ");
+
+        Self::InvalidRequest {
+            status: rejection.into_response().status(),
+            detail,
+        }
+    }
+
+    /// Stable, machine-readable identifier of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotLoggedIn => "NotLoggedIn",
+            Self::AlreadyLoggedIn { .. } => "AlreadyLoggedIn",
+            Self::InsufficientPermissions { .. } => "InsufficientPermissions",
+            Self::TargetNotModeratable { .. } => "TargetNotModeratable",
+            Self::InvalidCredentials => "InvalidCredentials",
+            Self::AccountDisabled => "AccountDisabled",
+            Self::Suspended { .. } => "Suspended",
+            Self::PasswordResetRequired => "PasswordResetRequired",
+            Self::CannotModifyOwnAccount => "CannotModifyOwnAccount",
+            Self::UsernameTaken => "UsernameTaken",
+            Self::RegistrationClosed => "RegistrationClosed",
+            Self::InviteRequired => "InviteRequired",
+            Self::InvalidInvite => "InvalidInvite",
+            Self::InvalidInviteSettings { .. } => "InvalidInviteSettings",
+            Self::InvalidSuspension { .. } => "InvalidSuspension",
+            Self::UserNotFound => "UserNotFound",
+            Self::DeletedAccountNotFound => "DeletedAccountNotFound",
+            Self::InviteNotFound => "InviteNotFound",
+            Self::MissingSession => "MissingSession",
+            Self::TooManySessions => "TooManySessions",
+            Self::RateLimited { .. } => "RateLimited",
+            Self::InvalidCsrfToken => "InvalidCsrfToken",
+            Self::InvalidMetricsToken => "InvalidMetricsToken",
+            Self::InvalidRequest { .. } => "InvalidRequest",
+            Self::RouteNotFound => "RouteNotFound",
+            Self::MethodNotAllowed => "MethodNotAllowed",
+            Self::DatabaseError(_) => "DatabaseError",
+            Self::Internal(_) => "InternalError",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidCredentials | Self::InvalidMetricsToken => StatusCode::UNAUTHORIZED,
+            Self::NotLoggedIn
+            | Self::AlreadyLoggedIn { .. }
+            | Self::InsufficientPermissions { .. }
+            | Self::TargetNotModeratable { .. }
+            | Self::AccountDisabled
+            | Self::Suspended { .. }
+            | Self::PasswordResetRequired
+            | Self::CannotModifyOwnAccount
+            | Self::RegistrationClosed
+            | Self::InviteRequired
+            | Self::InvalidInvite
+            | Self::InvalidCsrfToken => StatusCode::FORBIDDEN,
+            Self::UserNotFound
+            | Self::DeletedAccountNotFound
+            | Self::InviteNotFound
+            | Self::RouteNotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidRequest { status, .. } => *status,
+            Self::UsernameTaken => StatusCode::CONFLICT,
+            Self::TooManySessions | Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::InvalidInviteSettings { .. }
+            | Self::InvalidSuspension { .. }
+            | Self::MissingSession => StatusCode::BAD_REQUEST,
+            Self::DatabaseError(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Short, human-readable summary of the error.
+    fn title(&self) -> &'static str {
+        match self {
+            Self::NotLoggedIn => "You have to be logged in.",
+            Self::AlreadyLoggedIn { .. } => "You have to be logged out.",
+            Self::InsufficientPermissions { .. } => "Your permissions are insufficient.",
+            Self::TargetNotModeratable { .. } => "Target account cannot be moderated by you.",
+            Self::InvalidCredentials => "Username or password is incorrect.",
+            Self::AccountDisabled => "Account has been disabled.",
+            Self::Suspended { .. } => "Account is suspended.",
+            Self::PasswordResetRequired => "Password has to be changed first.",
+            Self::CannotModifyOwnAccount => "Administrators cannot modify their own account.",
+            Self::UsernameTaken => "Username is already taken.",
+            Self::RegistrationClosed => "Registration is closed.",
+            Self::InviteRequired => "Registration requires an invite code.",
+            Self::InvalidInvite => "Invite code is invalid, expired or has been used up.",
+            Self::InvalidInviteSettings { .. } => "Invite settings are out of range.",
+            Self::InvalidSuspension { .. } => "Suspension needs a reason and valid duration.",
+            Self::UserNotFound => "User does not exist.",
+            Self::DeletedAccountNotFound => "There is no deleted account of given name.",
+            Self::InviteNotFound => "Invite does not exist.",
+            Self::MissingSession => "Request has no session cookie.",
+            Self::TooManySessions => "Too many sessions were created from your address.",
+            Self::RateLimited { .. } => "Too many requests, try again later.",
+            Self::InvalidCsrfToken => "Missing or invalid CSRF token.",
+            Self::InvalidMetricsToken => "Missing or invalid metrics token.",
+            Self::InvalidRequest { .. } => "Request is malformed.",
+            Self::RouteNotFound => "There is no such route.",
+            Self::MethodNotAllowed => "Route does not support this method.",
+            Self::DatabaseError(_) | Self::Internal(_) => "Internal server error.",
+        }
+    }
+
+    /// Additional members of the problem details specific to the error.
+    fn extensions(&self) -> Value {
+        match self {
+            Self::AlreadyLoggedIn { your_level } => json!({ "your_level": your_level }),
+            Self::InsufficientPermissions {
+                your_level,
+                required_level,
+            } => json!({ "your_level": your_level, "required_level": required_level }),
+            Self::TargetNotModeratable {
+                your_level,
+                target_level,
+            } => json!({ "your_level": your_level, "target_level": target_level }),
+            Self::Suspended { until, reason } => json!({ "until": until, "reason": reason }),
+            Self::InvalidInviteSettings { max_uses_limit } => {
+                json!({ "max_uses_limit": max_uses_limit })
+            }
+            Self::InvalidSuspension { max_duration_hours } => {
+                json!({ "max_duration_hours": max_duration_hours })
+            }
+            Self::RateLimited {
+                retry_after_seconds,
+            } => json!({ "retry_after_seconds": retry_after_seconds }),
+            Self::InvalidRequest { detail, .. } => json!({ "detail": detail }),
+            _ => json!({}),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::DatabaseError(error) => {
+                tracing::error!("Database error while handling request. Error = [{}]", error)
+            }
+            Self::Internal(error) => {
+                tracing::error!("Internal error while handling request. Error = [{}]", error)
+            }
+            _ => {}
+        }
+
+        let status = self.status();
+        let body = Problem {
+            kind: format!("urn:budgeters:error:{}", self.code()),
+            title: self.title(),
+            status: status.as_u16(),
+            code: self.code(),
+            extensions: match self.extensions() {
+                Value::Object(extensions) => extensions,
+                _ => Map::new(),
+            },
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+
+        if let Self::RateLimited {
+            retry_after_seconds,
+        } = self
+        {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after_seconds));
+        }
+
+        response
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error)
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::InvalidUsername | AuthError::InvalidPassword => Self::InvalidCredentials,
+            AuthError::AccountDisabled => Self::AccountDisabled,
+            AuthError::Suspended { until, reason } => Self::Suspended { until, reason },
+            AuthError::UsernameTaken => Self::UsernameTaken,
+            AuthError::InvalidInvite => Self::InvalidInvite,
+            AuthError::DatabaseError(_) | AuthError::AdminExists => {
+                Self::Internal(error.to_string())
+            }
+        }
+    }
+}
+
+impl From<Suspension> for AppError {
+    fn from(suspension: Suspension) -> Self {
+        Self::Suspended {
+            until: suspension.until,
+            reason: suspension.reason,
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::invalid_request(rejection)
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::invalid_request(rejection)
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::invalid_request(rejection)
+    }
+}
+
+impl From<SessionError> for AppError {
+    fn from(error: SessionError) -> Self {
+        match error {
+            SessionError::TooManySessions => Self::TooManySessions,
+            _ => Self::Internal(error.to_string()),
+        }
+    }
+}
+
+/// Answers requests which match no route.
+pub async fn route_not_found() -> AppError {
+    AppError::RouteNotFound
+}
+
+/// Replaces the bodiless 405 of axum's method routing with problem details.
+pub async fn method_not_allowed<B>(req: Request<B>, next: Next<B>) -> Response {
+    let response = next.run(req).await;
+
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(CONTENT_TYPE)
+    {
+        return response;
+    }
+
+    let mut problem = AppError::MethodNotAllowed.into_response();
+    if let Some(allow) = response.headers().get(ALLOW) {
+        problem.headers_mut().insert(ALLOW, allow.clone());
+    }
+
+    problem
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::HttpBody, http::header::CONTENT_TYPE, response::IntoResponse};
+    use serde_json::{json, Value};
+
+    use super::AppError;
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[tokio::test]
+    async fn problem_details() {
+        let mut response = AppError::InsufficientPermissions {
+            your_level: None,
+            required_level: Permissions::Admin,
+        }
+        .into_response();
+
+        assert_eq!(response.status(), 403);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["code"], "InsufficientPermissions");
+        assert_eq!(body["status"], 403);
+        assert_eq!(body["required_level"], "Admin");
+        assert!(body["your_level"].is_null());
+    }
+
+    #[tokio::test]
+    async fn internal_details_are_hidden() {
+        let mut response = AppError::Internal("secret".into()).into_response();
+        let body = response.body_mut().data().await.unwrap().unwrap();
+
+        assert!(!String::from_utf8_lossy(&body).contains("secret"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn malformed_requests_are_problems() {
+        let app = TestApp::spawn().await;
+        let mut client = app.client();
+        client.fetch_csrf_token().await;
+
+        let missing_field = client
+            .post("/auth/login", json!({ "username": "alice" }))
+            .await;
+        assert_eq!(missing_field.status, 422);
+        assert_eq!(
+            missing_field.headers[CONTENT_TYPE],
+            "application/problem+json"
+        );
+        assert_eq!(missing_field.code(), "InvalidRequest");
+        assert!(missing_field.body["detail"]
+            .as_str()
+            .unwrap()
+            .contains("password"));
+
+        let invalid_page = client.get("/admin/users?page=first").await;
+        assert_eq!(invalid_page.status, 422);
+        assert_eq!(invalid_page.code(), "InvalidRequest");
+
+        assert_eq!(client.get("/nothing/here").await.code(), "RouteNotFound");
+
+        let wrong_method = client.get("/auth/login").await;
+        assert_eq!(wrong_method.status, 405);
+        assert_eq!(wrong_method.code(), "MethodNotAllowed");
+    }
+}
@@ -0,0 +1,75 @@
+//! Replacements of axum's `Json`, `Query` and `Path` extractors, which
+//! reject malformed requests with `AppError` instead of plain text.
+
+use std::ops::Deref;
+
+use async_trait::async_trait;
+use axum::{
+    body::HttpBody,
+    extract::{FromRequest, Path, Query, RequestParts},
+    BoxError, Json,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+/// JSON request body.
+pub struct ValidJson<T>(pub T);
+
+/// Query string of the request.
+pub struct ValidQuery<T>(pub T);
+
+/// Parameters captured by the route's path.
+pub struct ValidPath<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for ValidJson<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::from_request(req).await?;
+        Ok(ValidJson(value))
+    }
+}
+
+#[async_trait]
+impl<T, B> FromRequest<B> for ValidQuery<T>
+where
+    T: DeserializeOwned,
+    B: Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::from_request(req).await?;
+        Ok(ValidQuery(value))
+    }
+}
+
+#[async_trait]
+impl<T, B> FromRequest<B> for ValidPath<T>
+where
+    T: DeserializeOwned + Send,
+    B: Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::from_request(req).await?;
+        Ok(ValidPath(value))
+    }
+}
+
+impl<T> Deref for ValidJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
@@ -0,0 +1,18 @@
+mod service;
+
+use axum::{routing::get, Router};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(service::live, service::ready, service::version))]
+pub struct ApiDoc;
+
+/// Probes used by the orchestrator. They must be served
+/// outside of `ensure_session`, so that probing does not
+/// create a session on every request.
+pub fn routes() -> Router {
+    Router::new()
+        .route("/health/live", get(service::live))
+        .route("/health/ready", get(service::ready))
+        .route("/version", get(service::version))
+}
@@ -0,0 +1,114 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, Extension, Json};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use crate::{auth::Hasher, database::Database, migrations};
+
+/// Probes must answer quickly, even when the database does not.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+async fn schema_version(database: &PgPool) -> Option<i64> {
+    match tokio::time::timeout(PROBE_TIMEOUT, migrations::schema_version(database)).await {
+        Ok(Ok(version)) => version,
+        Ok(Err(error)) => {
+            tracing::warn!("Unable to read schema version. Error = [{}]", error);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("Reading schema version timed out.");
+            None
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "Process is running.", body = Object,
+        example = json!({ "status": "live" })))
+)]
+pub async fn live() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": "live"
+        })),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every check has passed.", body = Object,
+            example = json!({
+                "status": "ready",
+                "checks": { "database": true, "migrations": true, "hasher": true }
+            })),
+        (status = 503, description = "At least one check has failed.", body = Object,
+            example = json!({
+                "status": "unavailable",
+                "checks": { "database": true, "migrations": false, "hasher": true }
+            })),
+    )
+)]
+/// Ready when database is reachable, its schema is current
+/// and there is capacity left for hashing passwords.
+pub async fn ready(
+    database: Extension<Arc<Database>>,
+    hasher: Extension<Arc<Hasher>>,
+) -> (StatusCode, Json<Value>) {
+    let schema_version = schema_version(database.writer()).await;
+
+    let database_ready = schema_version.is_some();
+    let migrations_ready = schema_version == Some(migrations::latest_version());
+    let hasher_ready = !hasher.is_saturated();
+
+    let (status, description) = if database_ready && migrations_ready && hasher_ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (
+        status,
+        Json(json!({
+            "status": description,
+            "checks": {
+                "database": database_ready,
+                "migrations": migrations_ready,
+                "hasher": hasher_ready
+            }
+        })),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "health",
+    responses((status = 200, description = "Build of the server and version of its schema.",
+        body = Object, example = json!({
+            "version": "0.1.0",
+            "git_sha": "0123456789ab",
+            "build_time": "2024-01-01T00:00:00Z",
+            "schema_version": 3,
+            "expected_schema_version": 3
+        })))
+)]
+pub async fn version(database: Extension<Arc<Database>>) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "git_sha": env!("BUDGETERS_GIT_SHA"),
+            "build_time": env!("BUDGETERS_BUILD_TIME"),
+            "schema_version": schema_version(database.writer()).await,
+            "expected_schema_version": migrations::latest_version()
+        })),
+    )
+}
@@ -0,0 +1,178 @@
+mod service;
+
+use rand::{thread_rng, Rng};
+use sqlx::{postgres::PgExecutor, query, Row};
+use utoipa::OpenApi;
+
+use crate::auth::Permissions;
+
+pub use service::{create_invite, list_invites, revoke_invite};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(service::create_invite, service::list_invites, service::revoke_invite),
+    components(schemas(service::Invite))
+)]
+pub struct ApiDoc;
+
+const INVITE_CODE_BYTES: usize = 16;
+
+/// Invite which has been successfully used for signing up.
+pub struct ClaimedInvite {
+    pub invite_id: i64,
+    pub permissions: Option<Permissions>,
+}
+
+fn generate_invite_code() -> String {
+    let array: [u8; INVITE_CODE_BYTES] = thread_rng().gen();
+    base64::encode_config(array, base64::URL_SAFE_NO_PAD)
+}
+
+/// Uses up one use of the invite. Returns `None` if invite
+/// does not exist, has expired or has no uses left.
+pub async fn claim_invite<'e, E>(
+    code: &str,
+    executor: E,
+) -> Result<Option<ClaimedInvite>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let claim_stmt = include_str!("../../postgres/invites/claim_invite.sql");
+
+    let row = match query(claim_stmt)
+        .bind(code)
+        .fetch_optional(executor)
+        .await?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let permissions: Option<String> = row.try_get("permissions")?;
+
+    Ok(Some(ClaimedInvite {
+        invite_id: row.try_get("invite_id")?,
+        permissions: permissions
+            .map(|permissions| permissions.parse())
+            .transpose()
+            .map_err(|e: &str| sqlx::Error::Decode(e.into()))?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDateTime, Utc};
+    use serde_json::json;
+    use sqlx::{query, Row};
+
+    use crate::{
+        auth::{Permissions, RegistrationMode},
+        testing::{TestApp, TestClient, TestResponse},
+    };
+
+    async fn invite_only_app() -> TestApp {
+        TestApp::with_config(|config| {
+            config.accounts.registration_mode = RegistrationMode::InviteOnly
+        })
+        .await
+    }
+
+    async fn insert_invite(app: &TestApp, code: &str, expires_at: Option<NaiveDateTime>) {
+        let insert_stmt = include_str!("../../postgres/invites/insert_invite.sql");
+        query(insert_stmt)
+            .bind(code)
+            .bind(None::<String>)
+            .bind(expires_at)
+            .bind(1)
+            .bind(Permissions::Moderator.to_string())
+            .execute(app.database())
+            .await
+            .unwrap();
+    }
+
+    async fn signup(mut client: TestClient, username: &str, code: &str) -> TestResponse {
+        client.fetch_csrf_token().await;
+        client
+            .post(
+                "/auth/signup",
+                json!({ "username": username, "password": "correct horse", "invite_code": code }),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn claimed_invite_grants_permissions_once() {
+        let app = invite_only_app().await;
+        insert_invite(&app, "welcome", None).await;
+
+        assert_eq!(signup(app.client(), "alice", "welcome").await.status, 201);
+        assert_eq!(
+            signup(app.client(), "bob", "welcome").await.code(),
+            "InvalidInvite"
+        );
+
+        let permissions_stmt = include_str!("../../postgres/auth/check_permissions.sql");
+        let permissions: String = query(permissions_stmt)
+            .bind("alice")
+            .fetch_one(app.database())
+            .await
+            .unwrap()
+            .get("permissions");
+        assert_eq!(permissions, Permissions::Moderator.to_string());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn expired_invite_is_rejected() {
+        let app = invite_only_app().await;
+        let expired = Utc::now().naive_utc() - Duration::minutes(1);
+        insert_invite(&app, "welcome", Some(expired)).await;
+
+        assert_eq!(
+            signup(app.client(), "alice", "welcome").await.code(),
+            "InvalidInvite"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn failed_signup_keeps_invite_unused() {
+        let app = invite_only_app().await;
+        insert_invite(&app, "first", None).await;
+        insert_invite(&app, "second", None).await;
+        assert_eq!(signup(app.client(), "alice", "first").await.status, 201);
+
+        assert_eq!(
+            signup(app.client(), "alice", "second").await.code(),
+            "UsernameTaken"
+        );
+        assert_eq!(signup(app.client(), "bob", "second").await.status, 201);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn concurrent_signups_claim_invite_once() {
+        let app = invite_only_app().await;
+        insert_invite(&app, "welcome", None).await;
+
+        let signups: Vec<_> = (0..8)
+            .map(|user| {
+                let client = app.client();
+                tokio::spawn(async move { signup(client, &format!("user{user}"), "welcome").await })
+            })
+            .collect();
+
+        let mut created = 0;
+        for signup in signups {
+            let response = signup.await.unwrap();
+            if response.status == 201 {
+                created += 1;
+            } else {
+                assert_eq!(response.code(), "InvalidInvite");
+            }
+        }
+
+        assert_eq!(created, 1);
+    }
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgRow, query, Row};
+use utoipa::{IntoParams, ToSchema};
+
+use super::generate_invite_code;
+use crate::{
+    audit::{AuditLog, EventType},
+    auth::{AdminGuard, Permissions},
+    database::Database,
+    error::{ApiResult, AppError, Problem},
+    extract::{ValidJson, ValidPath, ValidQuery},
+    pagination::Pagination,
+};
+
+const MAX_INVITE_USES: u32 = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct InviteForm {
+    /// How many accounts can be created with the invite, one by default.
+    max_uses: Option<u32>,
+    /// Invite never expires when omitted.
+    expires_in_hours: Option<u32>,
+    /// Permissions granted to accounts created with the invite.
+    permissions: Option<Permissions>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct InviteQuery {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Invite {
+    invite_id: i64,
+    code: String,
+    created_by: Option<String>,
+    created_at: NaiveDateTime,
+    expires_at: Option<NaiveDateTime>,
+    max_uses: i32,
+    uses: i32,
+    permissions: Option<String>,
+}
+
+impl Invite {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Invite {
+            invite_id: row.try_get("invite_id")?,
+            code: row.try_get("code")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+            expires_at: row.try_get("expires_at")?,
+            max_uses: row.try_get("max_uses")?,
+            uses: row.try_get("uses")?,
+            permissions: row.try_get("permissions")?,
+        })
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/invites",
+    tag = "admin",
+    request_body = InviteForm,
+    responses(
+        (status = 201, description = "Invite has been created.", body = Object,
+            example = json!({ "error": "None", "invite_id": 1, "code": "tV9o3yQ1Zb8...", "expires_at": null, "max_uses": 1 })),
+        (status = 400, response = Problem),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn create_invite(
+    invite_form: ValidJson<InviteForm>,
+    database: Extension<Arc<Database>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    let max_uses = invite_form.max_uses.unwrap_or(1);
+
+    if max_uses == 0 || max_uses > MAX_INVITE_USES || invite_form.expires_in_hours == Some(0) {
+        return Err(AppError::InvalidInviteSettings {
+            max_uses_limit: MAX_INVITE_USES,
+        });
+    }
+
+    let code = generate_invite_code();
+    let expires_at = invite_form
+        .expires_in_hours
+        .map(|hours| chrono::Utc::now().naive_utc() + Duration::hours(i64::from(hours)));
+
+    let mut transaction = database.writer().begin().await?;
+
+    let insert_stmt = include_str!("../../postgres/invites/insert_invite.sql");
+    let invite_id: i64 = query(insert_stmt)
+        .bind(&code)
+        .bind(guard.username())
+        .bind(expires_at)
+        .bind(max_uses as i32)
+        .bind(invite_form.permissions.map(|p| p.to_string()))
+        .fetch_one(&mut transaction)
+        .await?
+        .try_get("invite_id")?;
+
+    audit
+        .event(EventType::InviteCreated)
+        .actor(guard.username())
+        .details(format!("invite_id = {invite_id}"))
+        .record(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "error": "None",
+            "invite_id": invite_id,
+            "code": code,
+            "expires_at": expires_at,
+            "max_uses": max_uses
+        })),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/invites",
+    tag = "admin",
+    params(InviteQuery),
+    responses(
+        (status = 200, description = "Page of `Invite`s, newest first.", body = Object,
+            example = json!({ "invites": [], "page": 1, "per_page": 20, "total": 0 })),
+        (status = 403, response = Problem),
+    ),
+    security(("session" = []))
+)]
+pub async fn list_invites(
+    ValidQuery(invite_query): ValidQuery<InviteQuery>,
+    database: Extension<Arc<Database>>,
+    _guard: AdminGuard,
+) -> ApiResult {
+    let pagination = Pagination::new(invite_query.page, invite_query.per_page);
+
+    let count_stmt = include_str!("../../postgres/invites/count_invites.sql");
+    let total: i64 = query(count_stmt)
+        .fetch_one(database.writer())
+        .await?
+        .try_get("total")?;
+
+    let list_stmt = include_str!("../../postgres/invites/list_invites.sql");
+    let invites = query(list_stmt)
+        .bind(pagination.limit())
+        .bind(pagination.offset())
+        .fetch_all(database.writer())
+        .await?
+        .iter()
+        .map(Invite::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "invites": invites,
+            "page": pagination.page,
+            "per_page": pagination.per_page,
+            "total": total
+        })),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/invites/{invite_id}",
+    tag = "admin",
+    params(("invite_id" = i64, Path, description = "Identifier of the invite.")),
+    responses(
+        (status = 200, description = "Invite cannot be used anymore.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn revoke_invite(
+    ValidPath(invite_id): ValidPath<i64>,
+    database: Extension<Arc<Database>>,
+    audit: AuditLog,
+    guard: AdminGuard,
+) -> ApiResult {
+    let mut transaction = database.writer().begin().await?;
+
+    let revoke_stmt = include_str!("../../postgres/invites/revoke_invite.sql");
+    let revoked = query(revoke_stmt)
+        .bind(invite_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if revoked == 0 {
+        transaction.rollback().await?;
+        return Err(AppError::InviteNotFound);
+    }
+
+    audit
+        .event(EventType::InviteRevoked)
+        .actor(guard.username())
+        .details(format!("invite_id = {invite_id}"))
+        .record(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
+}
@@ -0,0 +1,119 @@
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use tracing::Span;
+use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+
+use crate::config::{LogFormat, LoggingConfig};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+const FINGERPRINT_LENGTH: usize = 12;
+
+pub fn init(config: &LoggingConfig) {
+    let registry =
+        tracing_subscriber::registry().with(tracing_subscriber::EnvFilter::new(&config.filter));
+
+    match config.format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .init(),
+    }
+}
+
+/// Short, stable fingerprint of a secret or personal value, which lets
+/// log entries be correlated without revealing the value itself.
+pub fn redact(value: &str) -> String {
+    Sha256::digest(value.as_bytes())[..FINGERPRINT_LENGTH / 2]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Request ids given by clients are only trusted if they cannot
+/// break log lines or blow up their size.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(&byte))
+}
+
+fn generate_request_id() -> String {
+    format!("{:032x}", thread_rng().gen::<u128>())
+}
+
+/// Span of a single request. Session and user are recorded
+/// later, once they are known, as redacted values.
+pub fn request_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        session = tracing::field::Empty,
+        user = tracing::field::Empty,
+    )
+}
+
+/// Propagates `X-Request-Id` given by the client or generates a new one,
+/// so that it is available to the request span and returned in the response.
+/// Must wrap the `TraceLayer`.
+pub async fn request_id<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map_or_else(generate_request_id, str::to_owned);
+
+    // Only valid ids are kept, so conversion cannot fail.
+    let header = HeaderValue::from_str(&request_id).unwrap();
+    req.headers_mut().insert(REQUEST_ID_HEADER, header.clone());
+
+    let mut response = next.run(req).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_request_id, redact};
+
+    #[test]
+    fn redaction_hides_value() {
+        let session_id = "c2Vzc2lvbi1pZC10aGF0LWlzLXNlY3JldA";
+
+        assert_eq!(redact(session_id), redact(session_id));
+        assert_eq!(redact(session_id).len(), 12);
+        assert!(!session_id.contains(&redact(session_id)));
+    }
+
+    #[test]
+    fn request_ids_are_sanitized() {
+        assert!(is_valid_request_id("3f1c9a2e-7b7d-4d3b-9c1e-0a6f6c2b8e51"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("id\nforged log line"));
+        assert!(!is_valid_request_id(&"a".repeat(65)));
+    }
+}
@@ -1,52 +1,347 @@
+mod account;
+mod admin;
+mod audit;
 mod auth;
+mod cli;
+mod config;
 mod database;
+mod error;
+mod extract;
+mod health;
+mod invites;
+mod logging;
+mod metrics;
+mod migrations;
+mod moderation;
+mod openapi;
+mod pagination;
+mod rate_limit;
+mod security;
 mod session;
+mod shutdown;
+#[cfg(test)]
+mod testing;
+mod tls;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{middleware::from_fn, Extension, Router, Server};
-use chrono::Duration;
+use axum::{middleware::from_fn, Extension, Router};
+use clap::Parser;
 use dotenv::dotenv;
-use lazy_static::lazy_static;
+use tokio::sync::watch;
 
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+/// Routes acting on behalf of the session owner. They are
+/// served behind session handling and CSRF verification.
+fn session_routes() -> Router {
+    // Only authentication routes may start new sessions, the rest
+    // of them act on behalf of already logged in users.
+    let auth_router = auth::routes();
+    let admin_router = admin::routes().layer(Extension(session::Stateless));
+    let moderation_router = moderation::routes().layer(Extension(session::Stateless));
+    let account_router = account::routes().layer(Extension(session::Stateless));
+
+    Router::new()
+        .nest("/auth", auth_router)
+        .nest("/admin", admin_router)
+        .nest("/moderation", moderation_router)
+        .nest("/me", account_router)
+}
+
+/// Every route described by the OpenAPI document. Metrics are
+/// left out when they are served on a separate address.
+fn routes(serve_metrics: bool) -> Router {
+    let router = session_routes()
+        .layer(from_fn(session::verify_csrf))
+        .layer(from_fn(session::ensure_session))
+        .merge(health::routes());
 
-lazy_static! {
-    static ref SESSION_TIME: Duration = Duration::hours(2);
-    static ref PEPPER: Vec<u8> = vec![1, 2, 3];
+    if serve_metrics {
+        router.merge(metrics::routes())
+    } else {
+        router
+    }
 }
-// TODO: Middleware to ensure that every request has session cookie.
+
+/// Whole application with every layer it is served with. Background
+/// tasks using the same state are started separately.
+fn application(
+    config: Arc<config::Config>,
+    database: Arc<database::Database>,
+    hasher: auth::Hasher,
+    session_cache: Arc<session::SessionCache>,
+    metrics: Arc<metrics::Metrics>,
+    rate_limiter: Arc<rate_limit::RateLimiter>,
+) -> Router {
+    let serve_metrics = config.metrics.enabled && config.metrics.address.is_none();
+    let security_headers = Arc::new(security::SecurityHeaders::new(&config.security_headers));
+
+    routes(serve_metrics)
+        .merge(openapi::routes())
+        .fallback(axum::routing::any(error::route_not_found))
+        .layer(from_fn(error::method_not_allowed))
+        .layer(from_fn(rate_limit::limit_requests))
+        .layer(from_fn(metrics::track_requests))
+        .layer(Extension(metrics))
+        .layer(Extension(rate_limiter))
+        .layer(Extension(database))
+        .layer(Extension(session_cache))
+        .layer(Extension(Arc::new(hasher)))
+        .layer(Extension(config.clone()))
+        .layer(tower_http::trace::TraceLayer::new_for_http().make_span_with(logging::request_span))
+        .layer(from_fn(security::set_security_headers))
+        .layer(Extension(security_headers))
+        .layer(security::cors(&config.cors))
+        .layer(from_fn(logging::request_id))
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG")
-                .unwrap_or_else(|_| "budgeters_server=debug,tower_http=debug".into()),
+    let cli = cli::Cli::parse();
+
+    let config = match config::Config::load(cli.config.clone()) {
+        Ok(config) => Arc::new(config),
+        Err(error) => {
+            eprintln!("Invalid configuration:\n{error}");
+            std::process::exit(2);
+        }
+    };
+
+    logging::init(&config.logging);
+
+    // Diagnostics report connection problems instead of failing on them.
+    if let Some(cli::Command::Diagnose) = cli.command {
+        let config_path = config::Config::path(cli.config);
+        let exit_code = cli::diagnose(&config, config_path.as_deref()).await;
+        std::process::exit(exit_code);
+    }
+
+    let metrics = Arc::new(metrics::Metrics::new());
+    let hasher = auth::Hasher::new(
+        &config.hashing.pepper(),
+        &config.hashing,
+        metrics.password_hashing_duration.clone(),
+    );
+    let database_connection = match database::initialize_database(&config.database).await {
+        Ok(database) => Arc::new(database),
+        Err(error) => {
+            eprintln!("Unable to connect to the database. {error}");
+            std::process::exit(1);
+        }
+    };
+    let primary = database_connection.writer();
+
+    let migration_outcome = match cli.command {
+        Some(cli::Command::Migrate { .. }) => Ok(()),
+        _ if config.database.auto_migrate => migrations::migrate(primary, false).await.map(|_| ()),
+        _ => migrations::ensure_migrated(primary).await,
+    };
+
+    if let Err(error) = migration_outcome {
+        eprintln!("Unable to prepare database schema. {error}");
+        std::process::exit(1);
+    }
+
+    if !matches!(cli.command, Some(cli::Command::Migrate { .. })) {
+        if let Err(failures) = database::verify_statements(primary).await {
+            eprintln!("SQL statements do not match the database schema:");
+
+            for failure in failures {
+                eprintln!("  - {failure}");
+            }
+
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(command) = cli.command {
+        let exit_code = cli::run(command, primary, &hasher).await;
+        std::process::exit(exit_code);
+    }
+
+    auth::bootstrap_from_env(primary, &hasher).await;
+
+    let tls = if config.tls.enabled {
+        match tls::load(&config.tls).await {
+            Ok(rustls) => Some(rustls),
+            Err(error) => {
+                eprintln!("Unable to load TLS certificate. {error}");
+                std::process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+
+    let (shutdown_sender, shutdown_receiver) = watch::channel(());
+
+    let purge_task = tokio::spawn(account::purge_task(
+        database_connection.clone(),
+        config.clone(),
+        shutdown_receiver.clone(),
+    ));
+
+    let metrics_task = match config.metrics.address {
+        Some(address) if config.metrics.enabled => Some(tokio::spawn(metrics::serve(
+            address,
+            metrics.clone(),
+            database_connection.clone(),
+            config.clone(),
+            shutdown_receiver.clone(),
+        ))),
+        _ => None,
+    };
+
+    let tls_tasks = tls.clone().map(|rustls| {
+        let reload_task = tokio::spawn(tls::reload_task(
+            rustls,
+            config.clone(),
+            shutdown_receiver.clone(),
+        ));
+        let redirect_task = config.tls.redirect_address.map(|address| {
+            tokio::spawn(tls::serve_redirect(
+                address,
+                config.server.address.port(),
+                shutdown_receiver.clone(),
+            ))
+        });
+
+        (reload_task, redirect_task)
+    });
+
+    let rate_limiter = Arc::new(rate_limit::RateLimiter::new(
+        &config.rate_limit,
+        database_connection.clone(),
+    ));
+    let rate_limit_task = config.rate_limit.enabled.then(|| {
+        tokio::spawn(rate_limit::purge_task(
+            rate_limiter.clone(),
+            shutdown_receiver.clone(),
         ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    });
 
-    let hasher = auth::Hasher::new(PEPPER.as_slice());
-    let database_connection = Arc::new(database::initialize_database_pool().await);
+    let session_cache = Arc::new(session::SessionCache::new(&config.session));
+    let invalidation_task = if config.session.cache_ttl_seconds > 0 && config.session.cache_listen {
+        match session::listen_for_invalidations(&database_connection).await {
+            Ok(listener) => Some(tokio::spawn(session::invalidation_task(
+                session_cache.clone(),
+                listener,
+                shutdown_receiver.clone(),
+            ))),
+            Err(error) => {
+                eprintln!("Unable to listen for session invalidations. {error}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
-    let auth_router = auth::routes();
+    let server_router = application(
+        config.clone(),
+        database_connection.clone(),
+        hasher,
+        session_cache,
+        metrics,
+        rate_limiter,
+    );
 
-    let server_router = Router::new()
-        .nest("/auth", auth_router)
-        .layer(from_fn(session::ensure_session))
-        .layer(Extension(database_connection))
-        .layer(Extension(Arc::new(hasher)))
-        .layer(tower_http::trace::TraceLayer::new_for_http());
-    let server_address = std::env::var("BG_SERVERADDRESS").unwrap();
-
-    let server_outcome = Server::bind(
-        &server_address
-            .parse()
-            .expect("Unable to parse BG_SERVERADDRESS env variable."),
-    )
-    .serve(server_router.into_make_service())
-    .await;
-
-    println!("SERVER OUTCOME IS {server_outcome:?}");
+    let handle = axum_server::Handle::new();
+    let make_service = server_router.into_make_service_with_connect_info::<SocketAddr>();
+    let server = {
+        let (address, handle) = (config.server.address, handle.clone());
+
+        async move {
+            match tls {
+                Some(rustls) => {
+                    axum_server::bind_rustls(address, rustls)
+                        .handle(handle)
+                        .serve(make_service)
+                        .await
+                }
+                None => {
+                    axum_server::bind(address)
+                        .handle(handle)
+                        .serve(make_service)
+                        .await
+                }
+            }
+        }
+    };
+    tokio::pin!(server);
+
+    let exit_code = tokio::select! {
+        outcome = &mut server => {
+            tracing::error!("Server stopped unexpectedly. Outcome = [{:?}]", outcome);
+            shutdown::EXIT_SERVER_ERROR
+        }
+        _ = shutdown::signal() => {
+            tracing::info!("Shutting down, waiting for in-flight requests.");
+            let _ = shutdown_sender.send(());
+            handle.graceful_shutdown(Some(config.server.shutdown_timeout()));
+
+            match tokio::time::timeout(config.server.shutdown_timeout(), &mut server).await {
+                Ok(Ok(())) => shutdown::EXIT_SUCCESS,
+                Ok(Err(error)) => {
+                    tracing::error!("Server failed while shutting down. Error = [{}]", error);
+                    shutdown::EXIT_SERVER_ERROR
+                }
+                Err(_) => {
+                    tracing::warn!("Shutdown timeout expired, aborting remaining requests.");
+                    // Server closes remaining connections on its own at the same deadline.
+                    if let Err(error) = server.await {
+                        tracing::error!("Server failed while shutting down. Error = [{}]", error);
+                    }
+                    shutdown::EXIT_DRAIN_TIMEOUT
+                }
+            }
+        }
+    };
+
+    // Stops background tasks, also when server stopped on its own.
+    drop(shutdown_sender);
+    if let Err(error) = purge_task.await {
+        tracing::error!("Purge task failed. Error = [{}]", error);
+    }
+
+    if let Some(rate_limit_task) = rate_limit_task {
+        if let Err(error) = rate_limit_task.await {
+            tracing::error!("Rate limit purge task failed. Error = [{}]", error);
+        }
+    }
+
+    if let Some(invalidation_task) = invalidation_task {
+        if let Err(error) = invalidation_task.await {
+            tracing::error!("Session invalidation task failed. Error = [{}]", error);
+        }
+    }
+
+    if let Some(metrics_task) = metrics_task {
+        if let Err(error) = metrics_task.await {
+            tracing::error!("Metrics server failed. Error = [{}]", error);
+        }
+    }
+
+    if let Some((reload_task, redirect_task)) = tls_tasks {
+        if let Err(error) = reload_task.await {
+            tracing::error!("Certificate reload task failed. Error = [{}]", error);
+        }
+
+        if let Some(redirect_task) = redirect_task {
+            if let Err(error) = redirect_task.await {
+                tracing::error!("HTTPS redirect server failed. Error = [{}]", error);
+            }
+        }
+    }
+
+    // Aborted requests may still hold connections, so closing is bounded as well.
+    if tokio::time::timeout(shutdown::POOL_CLOSE_TIMEOUT, database_connection.close())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Unable to close every database connection in time.");
+    }
+
+    tracing::info!("Shutdown complete.");
+    std::process::exit(exit_code);
 }
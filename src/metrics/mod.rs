@@ -0,0 +1,226 @@
+mod service;
+
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
+use axum::{
+    extract::MatchedPath, http::Request, middleware::Next, response::Response, routing::get,
+    Extension, Router, Server,
+};
+use prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+};
+
+use tokio::sync::watch;
+use utoipa::OpenApi;
+
+use crate::{config::Config, database::Database, error::ApiResult};
+
+#[derive(OpenApi)]
+#[openapi(paths(service::render))]
+pub struct ApiDoc;
+
+/// Every metric exposed by the application.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    active_sessions: IntGaugeVec,
+    database_connections: IntGaugeVec,
+    pub sessions_created: IntCounter,
+    pub password_hashing_duration: Histogram,
+    signups: IntCounterVec,
+    logins: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("budgeters".into()), None)
+            .expect("Unable to create metrics registry.");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled HTTP requests."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let active_sessions = IntGaugeVec::new(
+            Opts::new("active_sessions", "Number of unexpired sessions."),
+            &["authenticated"],
+        )
+        .unwrap();
+        let database_connections = IntGaugeVec::new(
+            Opts::new("database_connections", "Connections of the database pool."),
+            &["state"],
+        )
+        .unwrap();
+        let sessions_created = IntCounter::new(
+            "sessions_created_total",
+            "Number of sessions given to new clients.",
+        )
+        .unwrap();
+        let password_hashing_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "password_hashing_duration_seconds",
+                "Time spent computing Argon2 hashes.",
+            )
+            .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]),
+        )
+        .unwrap();
+        let signups = IntCounterVec::new(
+            Opts::new("signups_total", "Outcomes of signup attempts."),
+            &["outcome"],
+        )
+        .unwrap();
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Outcomes of login attempts."),
+            &["outcome"],
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(active_sessions.clone()),
+            Box::new(database_connections.clone()),
+            Box::new(sessions_created.clone()),
+            Box::new(password_hashing_duration.clone()),
+            Box::new(signups.clone()),
+            Box::new(logins.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("Unable to register metric.");
+        }
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            active_sessions,
+            database_connections,
+            sessions_created,
+            password_hashing_duration,
+            signups,
+            logins,
+        }
+    }
+
+    fn record_outcome(counter: &IntCounterVec, outcome: &ApiResult) {
+        let outcome = match outcome {
+            Ok(_) => "Success",
+            Err(error) => error.code(),
+        };
+
+        counter.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_signup(&self, outcome: &ApiResult) {
+        Self::record_outcome(&self.signups, outcome);
+    }
+
+    pub fn record_login(&self, outcome: &ApiResult) {
+        Self::record_outcome(&self.logins, outcome);
+    }
+}
+
+/// Counts and times every request. Unmatched requests share single
+/// route label, so that scanners cannot create unbounded number of series.
+pub async fn track_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+    let method = req.method().to_string();
+    let metrics = req.extensions().get::<Arc<Metrics>>().cloned();
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+
+    if let Some(metrics) = metrics {
+        let status = response.status().as_u16().to_string();
+        let labels = [method.as_str(), route.as_str(), status.as_str()];
+
+        metrics.http_requests.with_label_values(&labels).inc();
+        metrics
+            .http_request_duration
+            .with_label_values(&labels)
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    response
+}
+
+pub fn routes() -> Router {
+    Router::new().route("/metrics", get(service::render))
+}
+
+/// Serves metrics on their own address until shutdown is signalled.
+pub async fn serve(
+    address: SocketAddr,
+    metrics: Arc<Metrics>,
+    database: Arc<Database>,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let router = routes()
+        .layer(Extension(metrics))
+        .layer(Extension(database))
+        .layer(Extension(config));
+
+    let outcome = Server::bind(&address)
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.changed().await;
+        })
+        .await;
+
+    if let Err(error) = outcome {
+        tracing::error!("Metrics server failed. Error = [{}]", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::AUTHORIZATION;
+
+    use crate::{config::Config, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn scraping_requires_token_and_labels_routes() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.metrics.enabled = true;
+            config.metrics.token = Some("scraper secret".into());
+        })
+        .await;
+        app.logged_in("alice", "correct horse").await;
+        app.client().get("/nothing/here").await;
+
+        let mut scraper = app.client();
+        assert_eq!(scraper.get("/metrics").await.code(), "InvalidMetricsToken");
+
+        scraper.set_header(AUTHORIZATION, "Bearer wrong secret");
+        assert_eq!(scraper.get("/metrics").await.code(), "InvalidMetricsToken");
+
+        scraper.set_header(AUTHORIZATION, "Bearer scraper secret");
+        let scraped = scraper.get("/metrics").await;
+        assert_eq!(scraped.status, 200);
+
+        let body = scraped.body.as_str().unwrap();
+        let requests = |labels: &str| {
+            body.lines()
+                .any(|line| line.starts_with(&format!("budgeters_http_requests_total{{{labels}}}")))
+        };
+        assert!(requests(
+            r#"method="POST",route="/auth/login",status="200""#
+        ));
+        assert!(requests(r#"method="GET",route="unmatched",status="404""#));
+    }
+}
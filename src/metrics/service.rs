@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use prometheus::{Encoder, TextEncoder};
+use sqlx::{query, Row};
+
+use super::Metrics;
+use crate::{
+    config::Config,
+    database::Database,
+    error::{AppError, Problem},
+    session,
+};
+
+/// Updates metrics which are not tracked continuously.
+async fn refresh(metrics: &Metrics, database: &Database) -> Result<(), sqlx::Error> {
+    let primary = database.writer();
+    metrics
+        .database_connections
+        .with_label_values(&["open"])
+        .set(i64::from(primary.size()));
+    metrics
+        .database_connections
+        .with_label_values(&["idle"])
+        .set(primary.num_idle() as i64);
+
+    let count_stmt = include_str!("../../postgres/metrics/count_active_sessions.sql");
+    let now = chrono::Utc::now().naive_utc();
+    let row = database
+        .read(|reader| query(count_stmt).bind(now).fetch_one(reader))
+        .await?;
+
+    metrics
+        .active_sessions
+        .with_label_values(&["true"])
+        .set(row.try_get("authenticated")?);
+    metrics
+        .active_sessions
+        .with_label_values(&["false"])
+        .set(row.try_get("anonymous")?);
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format.", body = String,
+            content_type = "text/plain; version=0.0.4"),
+        (status = 401, response = Problem),
+    ),
+    security((), ("metrics_token" = []))
+)]
+pub async fn render(
+    headers: HeaderMap,
+    metrics: Extension<Arc<Metrics>>,
+    database: Extension<Arc<Database>>,
+    config: Extension<Arc<Config>>,
+) -> Result<Response, AppError> {
+    if let Some(token) = &config.metrics.token {
+        let given = headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        if !given.is_some_and(|given| session::tokens_match(token, given)) {
+            return Err(AppError::InvalidMetricsToken);
+        }
+    }
+
+    refresh(metrics.as_ref(), &database).await?;
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder
+        .encode(&metrics.registry.gather(), &mut body)
+        .map_err(|error| AppError::Internal(format!("Unable to encode metrics. {error}")))?;
+
+    Ok((
+        StatusCode::OK,
+        [(CONTENT_TYPE, encoder.format_type().to_owned())],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,216 @@
+use std::fmt::Display;
+
+use sha2::{Digest, Sha256};
+use sqlx::{query, Executor, PgPool, Postgres, Row, Transaction};
+
+/// Schema change embedded into the binary. Once released, its SQL
+/// must never change - add a new migration instead.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+impl Migration {
+    fn checksum(&self) -> Vec<u8> {
+        Sha256::digest(self.sql.as_bytes()).to_vec()
+    }
+}
+
+impl Display for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}_{}", self.version, self.name)
+    }
+}
+
+/// Every migration in order of application.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../postgres/migrations/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "account_management",
+        sql: include_str!("../postgres/migrations/0002_account_management.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "session_client_ip",
+        sql: include_str!("../postgres/migrations/0003_session_client_ip.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "rate_limits",
+        sql: include_str!("../postgres/migrations/0004_rate_limits.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "audit_user_ids",
+        sql: include_str!("../postgres/migrations/0005_audit_user_ids.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "password_hash_params",
+        sql: include_str!("../postgres/migrations/0006_password_hash_params.sql"),
+    },
+];
+
+/// Version of the schema expected by this build of the application.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    DatabaseError(sqlx::Error),
+    /// Applied migration has different content than the embedded one.
+    ChecksumMismatch {
+        version: i64,
+        name: String,
+    },
+    /// Database was migrated by a newer version of the application.
+    UnknownVersion {
+        version: i64,
+        name: String,
+    },
+    /// Migrations are not applied and automatic migration is disabled.
+    Pending(Vec<&'static Migration>),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DatabaseError(error) => write!(f, "DatabaseError. {error}"),
+            Self::ChecksumMismatch { version, name } => write!(
+                f,
+                "Applied migration {version:04}_{name} differs from the embedded one."
+            ),
+            Self::UnknownVersion { version, name } => write!(
+                f,
+                "Applied migration {version:04}_{name} is unknown to this version of the application."
+            ),
+            Self::Pending(pending) => {
+                write!(f, "There are {} pending migrations:", pending.len())?;
+
+                for migration in pending {
+                    write!(f, " {migration}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error)
+    }
+}
+
+/// Verifies history of applied migrations and returns ones which are not applied yet.
+async fn pending_migrations(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let lock_stmt = include_str!("../postgres/migrator/lock_migrations.sql");
+    query(lock_stmt).execute(&mut *transaction).await?;
+
+    let create_stmt = include_str!("../postgres/migrator/create_history.sql");
+    query(create_stmt).execute(&mut *transaction).await?;
+
+    let list_stmt = include_str!("../postgres/migrator/list_applied.sql");
+    let applied = query(list_stmt).fetch_all(&mut *transaction).await?;
+
+    let mut applied_versions = Vec::with_capacity(applied.len());
+
+    for row in applied.iter() {
+        let version: i64 = row.try_get("version")?;
+        let name: String = row.try_get("name")?;
+        let checksum: Vec<u8> = row.try_get("checksum")?;
+
+        match MIGRATIONS
+            .iter()
+            .find(|migration| migration.version == version)
+        {
+            None => return Err(MigrationError::UnknownVersion { version, name }),
+            Some(migration) if migration.checksum() != checksum => {
+                return Err(MigrationError::ChecksumMismatch { version, name })
+            }
+            Some(_) => applied_versions.push(version),
+        }
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| !applied_versions.contains(&migration.version))
+        .collect())
+}
+
+/// Applies every pending migration in a single transaction and returns them.
+/// With `dry_run` nothing is applied, pending migrations are only reported.
+pub async fn migrate(
+    database: &PgPool,
+    dry_run: bool,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut transaction = database.begin().await?;
+    let pending = pending_migrations(&mut transaction).await?;
+
+    if dry_run || pending.is_empty() {
+        return Ok(pending);
+    }
+
+    let record_stmt = include_str!("../postgres/migrator/record_migration.sql");
+
+    for migration in pending.iter() {
+        tracing::info!("Applying migration {}.", migration);
+
+        transaction.execute(migration.sql).await?;
+
+        query(record_stmt)
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut transaction)
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(pending)
+}
+
+/// Fails if the schema is not up to date, without modifying it.
+pub async fn ensure_migrated(database: &PgPool) -> Result<(), MigrationError> {
+    let pending = migrate(database, true).await?;
+
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(MigrationError::Pending(pending))
+    }
+}
+
+/// Version of the most recent migration applied to the database.
+pub async fn schema_version(database: &PgPool) -> Result<Option<i64>, sqlx::Error> {
+    let version_stmt = include_str!("../postgres/migrator/current_version.sql");
+
+    query(version_stmt)
+        .fetch_one(database)
+        .await?
+        .try_get("version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MIGRATIONS;
+
+    #[test]
+    fn migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1);
+        }
+    }
+}
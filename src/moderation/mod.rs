@@ -0,0 +1,90 @@
+mod service;
+
+use axum::{
+    routing::{delete, post},
+    Router,
+};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(service::suspend_user, service::lift_suspension))]
+pub struct ApiDoc;
+
+pub fn routes() -> Router {
+    Router::new()
+        .route("/users/:username/suspend", post(service::suspend_user))
+        .route(
+            "/users/:username/suspension",
+            delete(service::lift_suspension),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use sqlx::query;
+
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn suspended_users_are_locked_out() {
+        let app = TestApp::spawn().await;
+        let mut moderator = app.logged_in("moderator", "correct horse").await;
+        app.set_permissions("moderator", Permissions::Moderator)
+            .await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+        let mut bob = app.logged_in("bob", "correct horse").await;
+
+        let suspension = json!({ "duration_hours": 24, "reason": "spam" });
+        let suspended = moderator
+            .post("/moderation/users/alice/suspend", suspension)
+            .await;
+        assert_eq!(suspended.status, 200);
+        assert_eq!(alice.get("/auth/activity").await.status, 403);
+        assert_eq!(
+            app.client().login("alice", "correct horse").await.code(),
+            "Suspended"
+        );
+
+        // Sessions which survive a suspension are rejected by guards.
+        let suspend_stmt = include_str!("../../postgres/moderation/suspend_user.sql");
+        query(suspend_stmt)
+            .bind("bob")
+            .bind(Utc::now().naive_utc() + Duration::hours(1))
+            .bind("spam")
+            .execute(app.database())
+            .await
+            .unwrap();
+        app.session_cache()
+            .invalidate_user("bob", app.database())
+            .await
+            .unwrap();
+        assert_eq!(bob.get("/auth/activity").await.code(), "Suspended");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn moderators_cannot_suspend_equal_or_higher_roles() {
+        let app = TestApp::spawn().await;
+        let mut moderator = app.logged_in("moderator", "correct horse").await;
+        app.set_permissions("moderator", Permissions::Moderator)
+            .await;
+
+        for (username, permissions) in [
+            ("colleague", Permissions::Moderator),
+            ("admin", Permissions::Admin),
+        ] {
+            app.client().signup(username, "correct horse").await;
+            app.set_permissions(username, permissions).await;
+
+            let suspension = json!({ "duration_hours": 24, "reason": "spam" });
+            let path = format!("/moderation/users/{username}/suspend");
+            assert_eq!(
+                moderator.post(&path, suspension).await.code(),
+                "TargetNotModeratable"
+            );
+        }
+    }
+}
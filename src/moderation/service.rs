@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use chrono::Duration;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{query, Postgres, Row, Transaction};
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{AuditLog, EventType},
+    auth::{ModeratorGuard, Permissions},
+    database::Database,
+    error::{ApiResult, AppError, Problem},
+    extract::{ValidJson, ValidPath},
+    session::{self, SessionCache},
+};
+
+const MAX_SUSPENSION_HOURS: u32 = 24 * 365;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SuspensionForm {
+    duration_hours: u32,
+    reason: String,
+}
+
+/// Locks account of the target for the rest of the transaction and
+/// checks whether moderator is allowed to act on it. Only accounts
+/// with lower permissions than the moderator's can be moderated.
+async fn lock_target(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    guard: &ModeratorGuard,
+) -> Result<(), AppError> {
+    let read_stmt = include_str!("../../postgres/moderation/read_target.sql");
+
+    let row = query(read_stmt)
+        .bind(username)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    let permissions: Permissions = row
+        .try_get::<String, _>("permissions")?
+        .parse()
+        .map_err(|error: &str| AppError::Internal(error.into()))?;
+
+    if permissions >= guard.permissions() {
+        return Err(AppError::TargetNotModeratable {
+            your_level: guard.permissions(),
+            target_level: permissions,
+        });
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/moderation/users/{username}/suspend",
+    tag = "moderation",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    request_body = SuspensionForm,
+    responses(
+        (status = 200, description = "User has been suspended and their sessions revoked.", body = Object,
+            example = json!({ "error": "None", "until": "2024-01-01T00:00:00" })),
+        (status = 400, response = Problem),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn suspend_user(
+    ValidPath(username): ValidPath<String>,
+    suspension_form: ValidJson<SuspensionForm>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: ModeratorGuard,
+) -> ApiResult {
+    let reason = suspension_form.reason.trim();
+
+    if reason.is_empty()
+        || suspension_form.duration_hours == 0
+        || suspension_form.duration_hours > MAX_SUSPENSION_HOURS
+    {
+        return Err(AppError::InvalidSuspension {
+            max_duration_hours: MAX_SUSPENSION_HOURS,
+        });
+    }
+
+    let until =
+        chrono::Utc::now().naive_utc() + Duration::hours(i64::from(suspension_form.duration_hours));
+
+    let mut transaction = database.writer().begin().await?;
+    lock_target(&mut transaction, &username, &guard).await?;
+
+    let suspend_stmt = include_str!("../../postgres/moderation/suspend_user.sql");
+    let event = audit
+        .event(EventType::AccountSuspended)
+        .actor(guard.username())
+        .target(&username)
+        .details(format!("until = {until}; reason = {reason}"));
+
+    query(suspend_stmt)
+        .bind(&username)
+        .bind(until)
+        .bind(reason)
+        .execute(&mut transaction)
+        .await?;
+    event.record(&mut transaction).await?;
+
+    let revoked = session::remove_user_sessions(&username, &mut transaction).await?;
+    audit
+        .event(EventType::SessionsRevoked)
+        .actor(guard.username())
+        .target(&username)
+        .details(format!("count = {revoked}"))
+        .record(&mut transaction)
+        .await?;
+    cache.commit_invalidation(&username, transaction).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None",
+            "until": until
+        })),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/moderation/users/{username}/suspension",
+    tag = "moderation",
+    params(("username" = String, Path, description = "Name of the target account.")),
+    responses(
+        (status = 200, description = "Suspension has been lifted.", body = Object,
+            example = json!({ "error": "None" })),
+        (status = 403, response = Problem),
+        (status = 404, response = Problem),
+    ),
+    security(("session" = [], "csrf" = []))
+)]
+pub async fn lift_suspension(
+    ValidPath(username): ValidPath<String>,
+    database: Extension<Arc<Database>>,
+    cache: Extension<Arc<SessionCache>>,
+    audit: AuditLog,
+    guard: ModeratorGuard,
+) -> ApiResult {
+    let mut transaction = database.writer().begin().await?;
+    lock_target(&mut transaction, &username, &guard).await?;
+
+    let lift_stmt = include_str!("../../postgres/moderation/lift_suspension.sql");
+    let event = audit
+        .event(EventType::SuspensionLifted)
+        .actor(guard.username())
+        .target(&username);
+
+    query(lift_stmt)
+        .bind(&username)
+        .execute(&mut transaction)
+        .await?;
+    event.record(&mut transaction).await?;
+    cache.commit_invalidation(&username, transaction).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "error": "None"
+        })),
+    ))
+}
@@ -0,0 +1,214 @@
+mod service;
+
+use std::sync::Arc;
+
+use axum::{
+    http::{header::CONTENT_SECURITY_POLICY, HeaderValue},
+    routing::get,
+    Extension, Router,
+};
+use tower_http::set_header::SetResponseHeaderLayer;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    account, admin, audit, auth, error::Problem, health, invites, metrics, moderation, session,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Budgeters API",
+        description = "Unsafe requests have to carry the session cookie together with \
+            its CSRF token, which can be obtained from `GET /auth/csrf`. \
+            Every error is described by problem details with stable `code`."
+    ),
+    components(responses(Problem)),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "auth", description = "Signing up, logging in and managing own credentials."),
+        (name = "account", description = "Account and data of the logged in user."),
+        (name = "admin", description = "Managing users and invites, requires `Admin` permissions."),
+        (name = "moderation", description = "Suspending users, requires `Moderator` permissions."),
+        (name = "health", description = "Probes for the orchestrator, they never start a session."),
+        (name = "metrics", description = "Only served here when metrics are enabled \
+            and have no separate address."),
+    )
+)]
+struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(
+                session::SESSION_COOKIE_NAME,
+            ))),
+        );
+        components.add_security_scheme(
+            "csrf",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(session::CSRF_HEADER_NAME))),
+        );
+        components.add_security_scheme(
+            "metrics_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Specification of every route returned by `crate::routes`.
+/// Each module documents its handlers under the paths they are served at.
+pub fn document() -> utoipa::openapi::OpenApi {
+    let mut document = ApiDoc::openapi();
+
+    for module in [
+        auth::ApiDoc::openapi(),
+        session::ApiDoc::openapi(),
+        account::ApiDoc::openapi(),
+        admin::ApiDoc::openapi(),
+        audit::ApiDoc::openapi(),
+        invites::ApiDoc::openapi(),
+        moderation::ApiDoc::openapi(),
+        health::ApiDoc::openapi(),
+        metrics::ApiDoc::openapi(),
+    ] {
+        document.merge(module);
+    }
+
+    document
+}
+
+/// Swagger UI loads its own scripts and styles, some of them inline,
+/// so it cannot be served under the policy meant for the API.
+const DOCS_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; img-src 'self' data:; \
+    style-src 'self' 'unsafe-inline'; frame-ancestors 'none'";
+
+/// Specification and its interactive viewer. Like the probes, they
+/// are served outside of `ensure_session`.
+pub fn routes() -> Router {
+    let swagger_config = utoipa_swagger_ui::Config::from("/openapi.json");
+
+    let docs_router = Router::new()
+        .route("/docs", get(service::docs_index))
+        .route("/docs/*file", get(service::docs))
+        .layer(SetResponseHeaderLayer::overriding(
+            CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static(DOCS_CONTENT_SECURITY_POLICY),
+        ))
+        .layer(Extension(Arc::new(swagger_config)));
+
+    Router::new()
+        .route("/openapi.json", get(service::specification))
+        .layer(Extension(Arc::new(document())))
+        .merge(docs_router)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+    use utoipa::openapi::HttpMethod;
+
+    const METHODS: [(Method, HttpMethod); 5] = [
+        (Method::GET, HttpMethod::Get),
+        (Method::POST, HttpMethod::Post),
+        (Method::PUT, HttpMethod::Put),
+        (Method::PATCH, HttpMethod::Patch),
+        (Method::DELETE, HttpMethod::Delete),
+    ];
+
+    /// Axum cannot list its routes, but prints their paths when debugged.
+    fn routed_paths(router: &axum::Router) -> BTreeSet<String> {
+        let debugged = format!("{router:?}");
+        let paths = debugged
+            .split_once("paths: {")
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .expect("Debug output of the router has changed.")
+            .0;
+
+        paths
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .map(|path| {
+                path.split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(parameter) => format!("{{{parameter}}}"),
+                        None => segment.to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn specification_matches_routes() {
+        let document = super::document();
+        // Without session handling and CSRF verification, every request
+        // reaches the router. Handlers fail on missing extensions instead.
+        let router = crate::session_routes()
+            .merge(crate::health::routes())
+            .merge(crate::metrics::routes());
+
+        let mut documented = BTreeSet::new();
+        for (path, item) in document.paths.paths.iter() {
+            for (method, http_method) in METHODS.iter() {
+                let operation = match http_method {
+                    HttpMethod::Get => &item.get,
+                    HttpMethod::Post => &item.post,
+                    HttpMethod::Put => &item.put,
+                    HttpMethod::Patch => &item.patch,
+                    HttpMethod::Delete => &item.delete,
+                    _ => unreachable!(),
+                };
+
+                if operation.is_some() {
+                    documented.insert((path.clone(), method.to_string()));
+                }
+            }
+        }
+
+        let documented_paths = documented.iter().map(|(path, _)| path.clone()).collect();
+        let mut routed = BTreeSet::new();
+
+        for path in routed_paths(&router).union(&documented_paths) {
+            let uri = path.replace(['{', '}'], "");
+
+            for (method, _) in METHODS.iter() {
+                let request = Request::builder()
+                    .method(method)
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let status = router.clone().oneshot(request).await.unwrap().status();
+
+                if status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED {
+                    routed.insert((path.clone(), method.to_string()));
+                }
+            }
+        }
+
+        assert_eq!(
+            documented.difference(&routed).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "Documented operations without route."
+        );
+        assert_eq!(
+            routed.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "Routes missing from the specification."
+        );
+    }
+}
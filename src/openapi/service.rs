@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use utoipa::openapi::OpenApi;
+use utoipa_swagger_ui::Config;
+
+use crate::{error::AppError, extract::ValidPath};
+
+pub async fn specification(document: Extension<Arc<OpenApi>>) -> Json<OpenApi> {
+    Json(document.as_ref().clone())
+}
+
+/// Relative links of the viewer only work with the trailing slash.
+pub async fn docs_index() -> Redirect {
+    Redirect::permanent("/docs/")
+}
+
+/// Serves files of the Swagger UI bundled into the binary.
+pub async fn docs(
+    ValidPath(file): ValidPath<String>,
+    config: Extension<Arc<Config<'static>>>,
+) -> Result<Response, AppError> {
+    let file = file.trim_start_matches('/');
+
+    match utoipa_swagger_ui::serve(file, config.0.clone()) {
+        Ok(Some(file)) => Ok((
+            StatusCode::OK,
+            [(CONTENT_TYPE, file.content_type)],
+            file.bytes.into_owned(),
+        )
+            .into_response()),
+        Ok(None) => Ok(StatusCode::NOT_FOUND.into_response()),
+        Err(error) => Err(AppError::Internal(format!(
+            "Unable to serve Swagger UI. {error}"
+        ))),
+    }
+}
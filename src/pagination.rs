@@ -0,0 +1,47 @@
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Page of the listing requested by the client,
+/// with defaults and limits already applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl Pagination {
+    pub fn new(page: Option<u32>, per_page: Option<u32>) -> Self {
+        Pagination {
+            page: page.unwrap_or(1).max(1),
+            per_page: per_page
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        }
+    }
+
+    pub fn limit(&self) -> i64 {
+        i64::from(self.per_page)
+    }
+
+    pub fn offset(&self) -> i64 {
+        i64::from(self.page - 1) * i64::from(self.per_page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pagination;
+
+    #[test]
+    fn pagination_limits() {
+        let default = Pagination::new(None, None);
+        assert_eq!(default.offset(), 0);
+        assert_eq!(default.limit(), 20);
+
+        let clamped = Pagination::new(Some(0), Some(1000));
+        assert_eq!(clamped.page, 1);
+        assert_eq!(clamped.limit(), 100);
+
+        assert_eq!(Pagination::new(Some(3), Some(10)).offset(), 20);
+    }
+}
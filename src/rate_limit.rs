@@ -0,0 +1,575 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::ConnectInfo,
+    http::{header::HeaderName, HeaderMap, HeaderValue, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::Row;
+use tokio::sync::watch;
+
+use crate::{
+    config::{RateLimitConfig, RateLimitKey, RateLimitPolicy, RateLimitStore},
+    database::Database,
+    error::AppError,
+    logging,
+    session::{self, SessionCache, SessionId},
+};
+
+pub const LIMIT_HEADER: &str = "ratelimit-limit";
+pub const REMAINING_HEADER: &str = "ratelimit-remaining";
+pub const RESET_HEADER: &str = "ratelimit-reset";
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Policy of requests with given method and path. Its scope
+/// keeps buckets of different policies apart.
+struct ScopedPolicy {
+    scope: String,
+    method: Option<Method>,
+    path: String,
+    prefix: bool,
+    policy: RateLimitPolicy,
+}
+
+impl ScopedPolicy {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let path_matches = if self.prefix {
+            path.starts_with(&self.path)
+        } else {
+            path == self.path
+        };
+
+        path_matches && self.method.as_ref().is_none_or(|limited| limited == method)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+impl Bucket {
+    /// Refills the bucket up to `now` and takes single token, if there is one.
+    fn take(&mut self, policy: &RateLimitPolicy, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second(policy)).min(f64::from(policy.burst));
+        self.updated_at = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        self.full_at = now + Duration::from_secs_f64(seconds_until(policy, self.tokens, 0.0));
+        allowed
+    }
+}
+
+fn per_second(policy: &RateLimitPolicy) -> f64 {
+    f64::from(policy.per_minute) / 60.0
+}
+
+/// Seconds until bucket with `tokens` holds all but `missing` of them.
+fn seconds_until(policy: &RateLimitPolicy, tokens: f64, missing: f64) -> f64 {
+    ((f64::from(policy.burst) - missing - tokens) / per_second(policy)).max(0.0)
+}
+
+enum Store {
+    Memory(Mutex<HashMap<String, Bucket>>),
+    Postgres(Arc<Database>),
+}
+
+/// Outcome of taking token from a bucket, sent back in `RateLimit-*` headers.
+#[derive(Debug, PartialEq)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    reset_seconds: u64,
+    retry_after_seconds: u64,
+}
+
+impl Decision {
+    fn new(policy: &RateLimitPolicy, allowed: bool, tokens: f64) -> Self {
+        let retry_after = if allowed {
+            0.0
+        } else {
+            seconds_until(policy, tokens, f64::from(policy.burst) - 1.0).max(1.0)
+        };
+
+        Decision {
+            allowed,
+            limit: policy.burst,
+            remaining: tokens.floor() as u32,
+            reset_seconds: seconds_until(policy, tokens, 0.0).ceil() as u64,
+            retry_after_seconds: retry_after.ceil() as u64,
+        }
+    }
+
+    fn set_headers(&self, headers: &mut HeaderMap) {
+        for (name, value) in [
+            (LIMIT_HEADER, u64::from(self.limit)),
+            (REMAINING_HEADER, u64::from(self.remaining)),
+            (RESET_HEADER, self.reset_seconds),
+        ] {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        }
+    }
+}
+
+/// Token bucket rate limiter applying the global policy
+/// and the first matching route policy to every request.
+pub struct RateLimiter {
+    enabled: bool,
+    global: ScopedPolicy,
+    routes: Vec<ScopedPolicy>,
+    store: Store,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, database: Arc<Database>) -> Self {
+        let routes = config
+            .routes
+            .iter()
+            .map(|route| {
+                let method = route.method.as_ref().map(|method| {
+                    Method::from_bytes(method.as_bytes())
+                        .expect("Methods should be validated while loading config.")
+                });
+                let (path, prefix) = match route.path.strip_suffix('*') {
+                    Some(prefix) => (prefix.to_owned(), true),
+                    None => (route.path.clone(), false),
+                };
+
+                ScopedPolicy {
+                    scope: format!("{} {}", route.method.as_deref().unwrap_or("*"), route.path),
+                    method,
+                    path,
+                    prefix,
+                    policy: route.policy(),
+                }
+            })
+            .collect();
+
+        let store = match config.store {
+            RateLimitStore::Memory => Store::Memory(Mutex::new(HashMap::new())),
+            RateLimitStore::Postgres => Store::Postgres(database),
+        };
+
+        RateLimiter {
+            enabled: config.enabled,
+            global: ScopedPolicy {
+                scope: "global".into(),
+                method: None,
+                path: "/".into(),
+                prefix: true,
+                policy: config.global.clone(),
+            },
+            routes,
+            store,
+        }
+    }
+
+    fn policies(&self, method: &Method, path: &str) -> impl Iterator<Item = &ScopedPolicy> {
+        let route = self.routes.iter().find(|route| route.matches(method, path));
+        std::iter::once(&self.global).chain(route)
+    }
+
+    async fn take(
+        &self,
+        bucket_key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<Decision, sqlx::Error> {
+        match &self.store {
+            Store::Memory(buckets) => {
+                let now = Instant::now();
+                let mut buckets = buckets.lock().unwrap();
+                let bucket = buckets.entry(bucket_key.to_owned()).or_insert(Bucket {
+                    tokens: f64::from(policy.burst),
+                    updated_at: now,
+                    full_at: now,
+                });
+                let allowed = bucket.take(policy, now);
+
+                Ok(Decision::new(policy, allowed, bucket.tokens))
+            }
+            Store::Postgres(database) => {
+                let take_stmt = include_str!("../postgres/rate_limit/take_token.sql");
+
+                let row = sqlx::query(take_stmt)
+                    .bind(bucket_key)
+                    .bind(f64::from(policy.burst))
+                    .bind(per_second(policy))
+                    .fetch_one(database.writer())
+                    .await?;
+
+                Ok(Decision::new(
+                    policy,
+                    row.try_get("allowed")?,
+                    row.try_get("tokens")?,
+                ))
+            }
+        }
+    }
+
+    /// Takes token from every bucket, keeping the most restrictive decision.
+    async fn take_all(
+        &self,
+        buckets: Vec<(String, &ScopedPolicy)>,
+        decision: &mut Option<Decision>,
+    ) {
+        for (bucket_key, scoped) in buckets {
+            match self.take(&bucket_key, &scoped.policy).await {
+                Ok(taken) => {
+                    let restrictive = |decision: &Decision| (decision.allowed, decision.remaining);
+
+                    if decision
+                        .as_ref()
+                        .is_none_or(|current| restrictive(&taken) < restrictive(current))
+                    {
+                        *decision = Some(taken);
+                    }
+                }
+                Err(error) => {
+                    tracing::error!(
+                        "Unable to check rate limit, request is let through. Error = [{}]",
+                        error
+                    )
+                }
+            }
+        }
+    }
+
+    /// Removes full buckets, which behave exactly like missing ones.
+    async fn purge(&self) -> Result<u64, sqlx::Error> {
+        match &self.store {
+            Store::Memory(buckets) => {
+                let now = Instant::now();
+                let mut buckets = buckets.lock().unwrap();
+                let count = buckets.len();
+                buckets.retain(|_, bucket| bucket.full_at > now);
+
+                Ok((count - buckets.len()) as u64)
+            }
+            Store::Postgres(database) => {
+                let purge_stmt = include_str!("../postgres/rate_limit/purge_full.sql");
+
+                let result = sqlx::query(purge_stmt).execute(database.writer()).await?;
+
+                Ok(result.rows_affected())
+            }
+        }
+    }
+}
+
+/// Id of the unexpired session the request carries cookie of. Cookies
+/// are chosen by the client, so unknown ones must not get own buckets.
+async fn existing_session<B>(req: &Request<B>) -> Option<SessionId> {
+    let session_id = session::cookie_session_id(req.headers())?;
+    let database = req.extensions().get::<Arc<Database>>()?;
+    let cache = req.extensions().get::<Arc<SessionCache>>()?;
+
+    match session::existing_session_id(session_id, database, cache).await {
+        Ok(session_id) => session_id,
+        Err(error) => {
+            tracing::error!(
+                "Unable to look up session for rate limiting, IP address is used instead. Error = [{}]",
+                error
+            );
+
+            None
+        }
+    }
+}
+
+/// Identifies the client by its session, hashed so that buckets do not hold
+/// secrets. Requests without valid session fall back to IP address.
+fn client_identity<B>(req: &Request<B>, key: RateLimitKey, session: Option<&str>) -> String {
+    match (key, session) {
+        (RateLimitKey::Session, Some(session_id)) => {
+            format!("session:{}", logging::redact(session_id))
+        }
+        _ => {
+            let ip = req
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map_or_else(
+                    || "unknown".into(),
+                    |ConnectInfo(address)| address.ip().to_string(),
+                );
+
+            format!("ip:{ip}")
+        }
+    }
+}
+
+/// Pairs policies with keys of the client's buckets in them.
+fn bucket_keys<'a, B>(
+    req: &Request<B>,
+    policies: &[&'a ScopedPolicy],
+    session: Option<&str>,
+) -> Vec<(String, &'a ScopedPolicy)> {
+    policies
+        .iter()
+        .map(|scoped| {
+            let bucket_key = format!(
+                "{}|{}",
+                scoped.scope,
+                client_identity(req, scoped.policy.key, session)
+            );
+
+            (bucket_key, *scoped)
+        })
+        .collect()
+}
+
+/// Rejects requests exceeding any of their policies with `429 Too Many Requests`.
+/// The most restrictive policy is described in `RateLimit-*` headers. When
+/// the store is unavailable, requests are let through rather than rejected.
+pub async fn limit_requests<B>(req: Request<B>, next: Next<B>) -> Result<Response, AppError> {
+    let limiter = req
+        .extensions()
+        .get::<Arc<RateLimiter>>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("Unable to get rate limiter from Request.".into()))?;
+
+    if !limiter.enabled {
+        return Ok(next.run(req).await);
+    }
+
+    // Address buckets are taken first, so that rejected clients
+    // cannot make every request look up the session.
+    let (by_session, by_ip): (Vec<_>, Vec<_>) = limiter
+        .policies(req.method(), req.uri().path())
+        .partition(|scoped| scoped.policy.key == RateLimitKey::Session);
+
+    let mut decision: Option<Decision> = None;
+    let buckets = bucket_keys(&req, &by_ip, None);
+    limiter.take_all(buckets, &mut decision).await;
+
+    if !by_session.is_empty() && decision.as_ref().is_none_or(|decision| decision.allowed) {
+        let session = existing_session(&req).await;
+        let buckets = bucket_keys(&req, &by_session, session.as_deref());
+        limiter.take_all(buckets, &mut decision).await;
+    }
+
+    let mut response = match &decision {
+        Some(decision) if !decision.allowed => AppError::RateLimited {
+            retry_after_seconds: decision.retry_after_seconds,
+        }
+        .into_response(),
+        _ => next.run(req).await,
+    };
+
+    if let Some(decision) = decision {
+        decision.set_headers(response.headers_mut());
+    }
+
+    Ok(response)
+}
+
+pub async fn purge_task(limiter: Arc<RateLimiter>, mut shutdown: watch::Receiver<()>) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        if let Err(error) = limiter.purge().await {
+            tracing::error!(
+                "Error occured while purging rate limit buckets. Error = [{}]",
+                error
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use axum::http::Method;
+
+    use super::{Bucket, Decision, RateLimiter, REMAINING_HEADER};
+    use crate::{
+        config::{Config, RateLimitConfig, RateLimitKey, RateLimitPolicy, RateLimitStore},
+        database::Database,
+        session::SESSION_COOKIE_NAME,
+        testing::TestApp,
+    };
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let policy = RateLimitPolicy {
+            burst: 2,
+            per_minute: 30,
+            key: RateLimitKey::Ip,
+        };
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            updated_at: start,
+            full_at: start,
+        };
+
+        assert!(bucket.take(&policy, start));
+        assert!(bucket.take(&policy, start));
+        assert!(!bucket.take(&policy, start));
+        assert_eq!(
+            Decision::new(&policy, false, bucket.tokens),
+            Decision {
+                allowed: false,
+                limit: 2,
+                remaining: 0,
+                reset_seconds: 4,
+                retry_after_seconds: 2,
+            }
+        );
+
+        assert!(bucket.take(&policy, start + Duration::from_secs(2)));
+        assert!(!bucket.take(&policy, start + Duration::from_secs(3)));
+        assert_eq!(bucket.full_at, start + Duration::from_secs(6));
+    }
+
+    #[tokio::test]
+    async fn route_policies_are_matched() {
+        let mut config = RateLimitConfig::default();
+        config.routes.push(crate::config::RouteRateLimit {
+            path: "/admin/*".into(),
+            method: None,
+            burst: 1,
+            per_minute: 1,
+            key: RateLimitKey::Session,
+        });
+        let database = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let limiter = RateLimiter::new(&config, Database::new(database, None).into());
+
+        let scopes = |method: Method, path: &str| {
+            limiter
+                .policies(&method, path)
+                .map(|scoped| scoped.scope.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scopes(Method::POST, "/auth/signup"),
+            ["global", "POST /auth/signup"]
+        );
+        assert_eq!(scopes(Method::GET, "/auth/signup"), ["global"]);
+        assert_eq!(
+            scopes(Method::DELETE, "/admin/users/x"),
+            ["global", "* /admin/*"]
+        );
+        assert_eq!(scopes(Method::GET, "/health/live"), ["global"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn signups_are_limited_per_address() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.rate_limit = RateLimitConfig::default();
+        })
+        .await;
+
+        for attempt in 0..5 {
+            let response = app
+                .client()
+                .signup(&format!("user{attempt}"), "password")
+                .await;
+            assert_eq!(response.status, 201);
+            assert_eq!(
+                response.headers[REMAINING_HEADER],
+                (4 - attempt).to_string()
+            );
+        }
+
+        let limited = app.client().signup("user5", "password").await;
+        assert_eq!(limited.status, 429);
+        assert_eq!(limited.code(), "RateLimited");
+        assert_eq!(limited.headers["retry-after"], "12");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn unknown_sessions_share_address_bucket() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.rate_limit = RateLimitConfig::default();
+            config.rate_limit.routes = vec![crate::config::RouteRateLimit {
+                path: "/auth/activity".into(),
+                method: None,
+                burst: 2,
+                per_minute: 1,
+                key: RateLimitKey::Session,
+            }];
+        })
+        .await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+
+        for attempt in 0..3 {
+            let mut forger = app.client();
+            forger.set_cookie(SESSION_COOKIE_NAME, &format!("forged{attempt}"));
+
+            let status = forger.get("/auth/activity").await.status;
+            assert_eq!(status == 429, attempt == 2);
+        }
+
+        assert_eq!(alice.get("/auth/activity").await.status, 200);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn concurrent_requests_share_postgres_bucket() {
+        let app = TestApp::spawn().await;
+        let config = RateLimitConfig {
+            store: RateLimitStore::Postgres,
+            ..RateLimitConfig::default()
+        };
+        let database = Database::new(app.database().clone(), None);
+
+        // Opens connections up front, so that the attempts really overlap.
+        let mut connections = Vec::new();
+        for _ in 0..8 {
+            connections.push(app.database().acquire().await.unwrap());
+        }
+        drop(connections);
+
+        let limiter = Arc::new(RateLimiter::new(&config, database.into()));
+        let policy = RateLimitPolicy {
+            burst: 2,
+            per_minute: 1,
+            key: RateLimitKey::Ip,
+        };
+
+        let attempts: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let policy = policy.clone();
+                tokio::spawn(async move { limiter.take("concurrent", &policy).await.unwrap() })
+            })
+            .collect();
+
+        let mut allowed = 0;
+        for attempt in attempts {
+            if attempt.await.unwrap().allowed {
+                allowed += 1;
+            }
+        }
+
+        assert_eq!(allowed, 2);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    http::{
+        header::{
+            HeaderName, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+            REFERRER_POLICY, RETRY_AFTER, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+            X_FRAME_OPTIONS,
+        },
+        HeaderMap, HeaderValue, Method, Request,
+    },
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{
+    config::{CorsConfig, SecurityHeadersConfig},
+    error::AppError,
+    logging::REQUEST_ID_HEADER,
+    rate_limit,
+    session::CSRF_HEADER_NAME,
+};
+
+/// Lets browser applications on allowed origins call the API.
+/// Origins and methods are validated while loading config.
+pub fn cors(config: &CorsConfig) -> CorsLayer {
+    let allow_origin = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(config.allowed_origins.iter().map(|origin| {
+            HeaderValue::from_str(origin)
+                .expect("Origins should be validated while loading config.")
+        }))
+    };
+
+    let methods = config
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            Method::from_bytes(method.as_bytes())
+                .expect("Methods should be validated while loading config.")
+        })
+        .collect::<Vec<_>>();
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_credentials(config.allow_credentials)
+        .allow_methods(methods)
+        .allow_headers([
+            CONTENT_TYPE,
+            AUTHORIZATION,
+            HeaderName::from_static(CSRF_HEADER_NAME),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers([
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            CONTENT_DISPOSITION,
+            RETRY_AFTER,
+            HeaderName::from_static(rate_limit::LIMIT_HEADER),
+            HeaderName::from_static(rate_limit::REMAINING_HEADER),
+            HeaderName::from_static(rate_limit::RESET_HEADER),
+        ])
+        .max_age(Duration::from_secs(u64::from(config.max_age_seconds)))
+}
+
+/// Headers added to every response by `set_security_headers`.
+pub struct SecurityHeaders(HeaderMap);
+
+impl SecurityHeaders {
+    pub fn new(config: &SecurityHeadersConfig) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+
+        for (name, value) in [
+            (STRICT_TRANSPORT_SECURITY, &config.strict_transport_security),
+            (CONTENT_SECURITY_POLICY, &config.content_security_policy),
+            (REFERRER_POLICY, &config.referrer_policy),
+            (X_FRAME_OPTIONS, &config.frame_options),
+        ] {
+            if !value.is_empty() {
+                let value = HeaderValue::from_str(value)
+                    .expect("Security headers should be validated while loading config.");
+                headers.insert(name, value);
+            }
+        }
+
+        SecurityHeaders(headers)
+    }
+}
+
+/// Adds security headers to the response. Headers which route
+/// has already set, e.g. its own content security policy, are kept.
+pub async fn set_security_headers<B>(req: Request<B>, next: Next<B>) -> Result<Response, AppError> {
+    let headers = req
+        .extensions()
+        .get::<Arc<SecurityHeaders>>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("Unable to get security headers from Request.".into()))?;
+
+    let mut response = next.run(req).await;
+
+    for (name, value) in headers.0.iter() {
+        if !response.headers().contains_key(name) {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+        CONTENT_SECURITY_POLICY, ORIGIN, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    };
+
+    use crate::{config::Config, testing::TestApp};
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn responses_carry_security_and_cors_headers() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.security_headers.frame_options = "SAMEORIGIN".into();
+            config.cors.allowed_origins = vec!["https://app.example.com".into()];
+        })
+        .await;
+
+        let health = app.client().get("/health/live").await;
+        assert_eq!(health.headers[X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert_eq!(health.headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(
+            health.headers[CONTENT_SECURITY_POLICY],
+            "default-src 'none'; frame-ancestors 'none'"
+        );
+
+        let docs = app.client().get("/docs/").await;
+        assert_eq!(docs.status, 200);
+        assert_eq!(docs.headers[X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert!(docs.headers[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .contains("style-src 'self' 'unsafe-inline'"));
+
+        let preflight = |origin: &'static str| {
+            let mut client = app.client();
+            client.set_header(ORIGIN, origin);
+            client.set_header(ACCESS_CONTROL_REQUEST_METHOD, "POST");
+            async move { client.options("/auth/login").await }
+        };
+
+        let allowed = preflight("https://app.example.com").await;
+        assert_eq!(allowed.status, 200);
+        assert_eq!(
+            allowed.headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert!(allowed.headers[ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .contains("POST"));
+
+        let disallowed = preflight("https://evil.example.com").await;
+        assert!(!disallowed.headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
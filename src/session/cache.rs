@@ -0,0 +1,421 @@
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use sqlx::{
+    postgres::{PgExecutor, PgListener},
+    query, PgPool, Postgres, Transaction,
+};
+use tokio::sync::watch;
+
+use super::{SessionId, SessionIdReference, SessionInfo};
+use crate::{auth::AccountStatus, config::SessionConfig, database::Database};
+
+/// Channel of invalidation notifications, whose payload is the username.
+const INVALIDATION_CHANNEL: &str = "budgeters_session_cache";
+const LISTEN_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Entry<T> {
+    value: T,
+    cached_at: Instant,
+}
+
+/// Entries older than the TTL are never returned. When the map is full,
+/// the oldest entry is dropped, which is expired if any of them is.
+struct ExpiringMap<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Keys in the order they were inserted in. Records of removed or
+    /// reinserted entries are skipped when met and dropped now and then.
+    order: VecDeque<(K, Instant)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> ExpiringMap<K, V> {
+    fn new() -> Self {
+        ExpiringMap {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get<Q>(&self, key: &Q, ttl: Duration) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries
+            .get(key)
+            .filter(|entry| entry.cached_at.elapsed() < ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, capacity: usize) {
+        while self.entries.len() >= capacity && !self.entries.contains_key(&key) {
+            match self.order.pop_front() {
+                Some((oldest, cached_at)) if self.is_current(&oldest, cached_at) => {
+                    self.entries.remove(&oldest);
+                }
+                Some(_) => {}
+                None => return,
+            }
+        }
+
+        let cached_at = Instant::now();
+        self.entries.insert(key.clone(), Entry { value, cached_at });
+        self.order.push_back((key, cached_at));
+
+        if self.order.len() > capacity.saturating_mul(2) {
+            let entries = &self.entries;
+            self.order.retain(|(key, cached_at)| {
+                entries
+                    .get(key)
+                    .is_some_and(|entry| entry.cached_at == *cached_at)
+            });
+        }
+    }
+
+    fn is_current(&self, key: &K, cached_at: Instant) -> bool {
+        self.entries
+            .get(key)
+            .is_some_and(|entry| entry.cached_at == cached_at)
+    }
+
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.remove(key);
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&V) -> bool) {
+        self.entries.retain(|_, entry| keep(&entry.value));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Sessions of logged in users and status of their accounts, so that
+/// guarded requests usually do not reach the database. Anonymous sessions
+/// are never cached, since logging in changes them without an eviction.
+///
+/// Every change to a session or an account has to be followed by
+/// [`SessionCache::invalidate_user`] or [`SessionCache::commit_invalidation`],
+/// which also notify other instances, or by [`notify_invalidation`]
+/// where there is no cache at hand.
+pub struct SessionCache {
+    ttl: Duration,
+    capacity: usize,
+    /// Bumped by every eviction, so that values read from the database
+    /// before the eviction are not stored after it.
+    generation: AtomicU64,
+    sessions: Mutex<ExpiringMap<SessionId, SessionInfo>>,
+    accounts: Mutex<ExpiringMap<String, AccountStatus>>,
+}
+
+impl SessionCache {
+    pub fn new(config: &SessionConfig) -> Self {
+        SessionCache {
+            ttl: config.cache_ttl(),
+            capacity: config.cache_capacity as usize,
+            generation: AtomicU64::new(0),
+            sessions: Mutex::new(ExpiringMap::new()),
+            accounts: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Has to be taken before reading the value which is going to be stored.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn session(&self, session_id: SessionIdReference<'_>) -> Option<SessionInfo> {
+        self.sessions.lock().unwrap().get(session_id, self.ttl)
+    }
+
+    pub fn store_session(&self, info: &SessionInfo, generation: u64) {
+        if !self.enabled() || info.username.is_none() {
+            return;
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if self.generation() == generation {
+            sessions.insert(info.session_id.clone(), info.clone(), self.capacity);
+        }
+    }
+
+    pub fn account(&self, username: &str) -> Option<AccountStatus> {
+        self.accounts.lock().unwrap().get(username, self.ttl)
+    }
+
+    pub fn store_account(&self, username: &str, status: &AccountStatus, generation: u64) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if self.generation() == generation {
+            accounts.insert(username.to_owned(), status.clone(), self.capacity);
+        }
+    }
+
+    pub fn evict_session(&self, session_id: SessionIdReference<'_>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        sessions.remove(session_id);
+    }
+
+    /// Evicts sessions and account status of given user from this instance only.
+    pub fn evict_user(&self, username: &str) {
+        let (mut sessions, mut accounts) =
+            (self.sessions.lock().unwrap(), self.accounts.lock().unwrap());
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        sessions.retain(|info| info.username.as_deref() != Some(username));
+        accounts.remove(username);
+    }
+
+    pub fn clear(&self) {
+        let (mut sessions, mut accounts) =
+            (self.sessions.lock().unwrap(), self.accounts.lock().unwrap());
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        sessions.clear();
+        accounts.clear();
+    }
+
+    /// Evicts given user and notifies other instances, once the change
+    /// has been committed. Meant for changes made outside of a transaction.
+    pub async fn invalidate_user(
+        &self,
+        username: &str,
+        database: &PgPool,
+    ) -> Result<(), sqlx::Error> {
+        self.evict_user(username);
+        notify_invalidation(username, database).await
+    }
+
+    /// Commits transaction which changed given user, then evicts the user
+    /// and notifies other instances. Evicting before the commit would let
+    /// requests still reading the old state cache it again.
+    pub async fn commit_invalidation(
+        &self,
+        username: &str,
+        mut transaction: Transaction<'_, Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        notify_invalidation(username, &mut transaction).await?;
+        transaction.commit().await?;
+        self.evict_user(username);
+
+        Ok(())
+    }
+}
+
+/// Makes every listening instance evict given user. Used directly
+/// by CLI commands, which have no cache of their own.
+pub async fn notify_invalidation<'e, E>(username: &str, executor: E) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let notify_stmt = include_str!("../../postgres/session/notify_invalidation.sql");
+
+    query(notify_stmt)
+        .bind(INVALIDATION_CHANNEL)
+        .bind(username)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// Subscribes to invalidations before the server starts. The listener
+/// holds one connection of the primary pool while it is running.
+pub async fn listen_for_invalidations(database: &Database) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(database.writer()).await?;
+    listener.listen(INVALIDATION_CHANNEL).await?;
+
+    Ok(listener)
+}
+
+/// Applies invalidations sent by other instances. Notifications sent while
+/// the connection was lost are gone, so the whole cache is cleared then.
+pub async fn invalidation_task(
+    cache: Arc<SessionCache>,
+    mut listener: PgListener,
+    mut shutdown: watch::Receiver<()>,
+) {
+    loop {
+        let received = tokio::select! {
+            received = listener.try_recv() => received,
+            _ = shutdown.changed() => break,
+        };
+
+        match received {
+            Ok(Some(notification)) => cache.evict_user(notification.payload()),
+            Ok(None) => {
+                tracing::warn!(
+                    "Lost connection listening for session invalidations, clearing session cache."
+                );
+                cache.clear();
+            }
+            Err(error) => {
+                tracing::error!(
+                    "Unable to listen for session invalidations. Error = [{}]",
+                    error
+                );
+                cache.clear();
+
+                tokio::select! {
+                    _ = tokio::time::sleep(LISTEN_RETRY_INTERVAL) => {}
+                    _ = shutdown.changed() => break,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{notify_invalidation, ExpiringMap};
+    use crate::{auth::Permissions, testing::TestApp};
+
+    #[test]
+    fn full_map_drops_oldest_entry() {
+        let ttl = Duration::from_secs(60);
+        let mut map = ExpiringMap::new();
+
+        for (value, key) in ["alice", "bob", "alice", "carol"].into_iter().enumerate() {
+            map.insert(key, value, 2);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(map.entries.len(), 2);
+        assert_eq!(map.get("bob", ttl), None);
+        assert_eq!(map.get("alice", ttl), Some(2));
+        assert_eq!(map.get("carol", ttl), Some(3));
+    }
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let mut map = ExpiringMap::new();
+        map.insert("alice", 1, 10);
+
+        assert_eq!(map.get("alice", Duration::ZERO), None);
+        assert_eq!(map.get("alice", Duration::from_secs(60)), Some(1));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn notified_changes_reach_cached_sessions() {
+        let app = TestApp::spawn().await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+        assert_eq!(
+            alice.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+
+        // Changes made behind the back of the cache are not seen...
+        let update_stmt = include_str!("../../postgres/admin/update_permissions.sql");
+        sqlx::query(update_stmt)
+            .bind("alice")
+            .bind("Admin")
+            .execute(app.database())
+            .await
+            .unwrap();
+        assert_eq!(
+            alice.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+
+        // ...until some instance announces them.
+        notify_invalidation("alice", app.database()).await.unwrap();
+
+        for attempt in 0.. {
+            if alice.get("/admin/users").await.status == 200 {
+                break;
+            }
+
+            assert!(attempt < 100, "Invalidation has not been received.");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(alice.logout().await.status, 200);
+        assert_eq!(
+            alice.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn reads_during_transaction_are_not_kept() {
+        let app = TestApp::with_config(|config| config.session.cache_listen = false).await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+
+        let mut transaction = app.database().begin().await.unwrap();
+        let update_stmt = include_str!("../../postgres/admin/update_permissions.sql");
+        sqlx::query(update_stmt)
+            .bind("alice")
+            .bind(Permissions::Admin.to_string())
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+
+        // Old permissions are read and cached while the change is in flight.
+        assert_eq!(
+            alice.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+
+        app.session_cache()
+            .commit_invalidation("alice", transaction)
+            .await
+            .unwrap();
+        assert_eq!(alice.get("/admin/users").await.status, 200);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn lagging_replica_does_not_refill_cache() {
+        let app = TestApp::with_lagging_replica().await;
+        let mut admin = app.logged_in("admin", "correct horse").await;
+        app.set_permissions("admin", Permissions::Admin).await;
+        let mut alice = app.logged_in("alice", "correct horse").await;
+        let mut bob = app.logged_in("bob", "correct horse").await;
+        assert_eq!(
+            bob.get("/admin/users").await.code(),
+            "InsufficientPermissions"
+        );
+
+        app.freeze_replica().await;
+
+        let revoked = admin.delete("/admin/users/alice/sessions", json!({})).await;
+        assert_eq!(revoked.status, 200);
+        let promoted = json!({ "permissions": "Admin" });
+        let promoted = admin.put("/admin/users/bob/permissions", promoted).await;
+        assert_eq!(promoted.status, 200);
+
+        assert_eq!(
+            alice.get("/auth/activity").await.code(),
+            "InsufficientPermissions"
+        );
+        assert_eq!(bob.get("/admin/users").await.status, 200);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use axum::{
+    headers::{Cookie as HeaderCookie, HeaderMapExt},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use rand::{thread_rng, Rng};
+use serde_json::{json, Value};
+
+use super::{management::SESSION_COOKIE_NAME, SessionCache, SessionInfo};
+use crate::{
+    database::Database,
+    error::{AppError, Problem},
+    logging,
+};
+
+pub const CSRF_HEADER_NAME: &str = "x-csrf-token";
+const CSRF_TOKEN_BYTES: usize = 32;
+
+pub(super) fn generate_csrf_token() -> String {
+    let array: [u8; CSRF_TOKEN_BYTES] = thread_rng().gen();
+    base64::encode_config(array, base64::URL_SAFE_NO_PAD)
+}
+
+/// Compares tokens without leaking position of the first difference.
+pub(crate) fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Requires every unsafe request to carry token of its session
+/// in the `X-CSRF-Token` header. Must run after `ensure_session`.
+pub async fn verify_csrf<B>(req: Request<B>, next: Next<B>) -> Result<Response, AppError> {
+    if req.method().is_safe() {
+        return Ok(next.run(req).await);
+    }
+
+    let given_token = req
+        .headers()
+        .get(CSRF_HEADER_NAME)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AppError::InvalidCsrfToken)?;
+
+    let session_id = req
+        .headers()
+        .typed_get::<HeaderCookie>()
+        .and_then(|cookies| cookies.get(SESSION_COOKIE_NAME).map(String::from))
+        .ok_or(AppError::InvalidCsrfToken)?;
+
+    let database = req
+        .extensions()
+        .get::<Arc<Database>>()
+        .ok_or_else(|| AppError::Internal("Unable to get database handler from Request.".into()))?;
+    let cache = req
+        .extensions()
+        .get::<Arc<SessionCache>>()
+        .ok_or_else(|| AppError::Internal("Unable to get session cache from Request.".into()))?;
+
+    match SessionInfo::read_cached(&session_id, database, cache).await? {
+        Some(info) if tokens_match(&info.csrf_token, given_token) => Ok(next.run(req).await),
+        _ => {
+            tracing::warn!(
+                "Rejected request with invalid CSRF token. Session = [{}]",
+                logging::redact(&session_id)
+            );
+
+            Err(AppError::InvalidCsrfToken)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/csrf",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Token which has to be sent in the `X-CSRF-Token` header \
+            of unsafe requests. Starts a session if there is none.", body = Object,
+            example = json!({ "csrf_token": "3q2-7wYb...", "header": "x-csrf-token" })),
+        (status = 429, response = Problem),
+    )
+)]
+pub async fn csrf_token(session_info: SessionInfo) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "csrf_token": session_info.csrf_token(),
+            "header": CSRF_HEADER_NAME
+        })),
+    )
+}
@@ -1,118 +1,91 @@
+use std::sync::{Arc, Mutex};
+
 use axum::{
     headers::{Cookie as HeaderCookie, HeaderMapExt},
-    http::{
-        header::{COOKIE, SET_COOKIE},
-        HeaderValue, Request, StatusCode,
-    },
+    http::{header::SET_COOKIE, Extensions, HeaderMap, HeaderValue, Request},
     middleware::Next,
     response::Response,
 };
-use sqlx::PgPool;
-
-use crate::session::verify_session_id;
-
 use cookie::{Cookie, CookieBuilder};
-use std::sync::Arc;
 
 use super::SessionId;
+use crate::{config::Config, error::AppError};
 
-pub(super) const SESSION_COOKIE_NAME: &str = "budgeters_session";
+pub const SESSION_COOKIE_NAME: &str = "budgeters_session";
 
-fn create_session_cookie(session_id: SessionId) -> Cookie<'static> {
-    CookieBuilder::new(SESSION_COOKIE_NAME, session_id)
-        .secure(true)
+fn create_session_cookie(session_id: SessionId, config: &Config) -> Cookie<'static> {
+    let mut builder = CookieBuilder::new(SESSION_COOKIE_NAME, session_id)
+        .secure(config.cookie.secure)
+        .same_site(config.cookie.same_site.into())
         // TODO: Expiration parameter.
-        .http_only(true)
-        .finish()
+        .http_only(true);
+
+    if let Some(domain) = &config.cookie.domain {
+        builder = builder.domain(domain.clone());
+    }
+
+    builder.finish()
 }
 
-async fn create_session<B>(
-    mut req: Request<B>,
-    next: Next<B>,
-) -> Result<Response, (StatusCode, String)> {
-    let database = match req.extensions().get::<Arc<PgPool>>() {
-        Some(db) => db,
-        None => {
-            tracing::error!("Unable to get database handler from Request.");
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Unable to establish connection to database.".into(),
-            ));
-        }
-    };
-
-    match super::fresh_session(database).await {
-        Ok(id) => {
-            let cookie = create_session_cookie(id);
-            req.headers_mut().insert(
-                COOKIE,
-                HeaderValue::from_str(&cookie.stripped().to_string()).unwrap(),
-            );
-
-            let mut response = next.run(req).await;
-            response.headers_mut().insert(
-                SET_COOKIE,
-                HeaderValue::from_str(&cookie.to_string()).unwrap(),
-            );
-
-            Ok(response)
-        }
-        Err(error) => {
-            tracing::error!("Error giving fresh session_id. Error=[{}]", error);
-
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Unable to give new session id.".into(),
-            ))
-        }
+/// Session created while handling the request, whose
+/// cookie has to be sent back with the response.
+#[derive(Clone, Default)]
+pub struct CreatedSession(Arc<Mutex<Option<SessionId>>>);
+
+impl CreatedSession {
+    /// Sends cookie of given session instead of the one the request came with.
+    pub(super) fn replace(&self, session_id: &SessionId) {
+        *self.0.lock().unwrap() = Some(session_id.clone());
     }
 }
 
-pub async fn ensure_session<B>(
-    req: Request<B>,
-    next: Next<B>,
-) -> Result<Response, (StatusCode, String)> {
-    let cookies = match req.headers().typed_get::<HeaderCookie>() {
-        Some(cookies) => cookies,
-        None => {
-            return create_session(req, next).await;
-            tracing::debug!("Unable to get cookies from response.");
-            return Err((
-                StatusCode::BAD_REQUEST,
-                "Unable to read cookies from request.".into(),
-            ));
-        }
-    };
-
-    let database = match req.extensions().get::<Arc<PgPool>>() {
-        Some(db) => db,
-        None => {
-            tracing::error!("Unable to get database handler from Request.");
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Unable to establish connection to database.".into(),
-            ));
-        }
-    };
-
-    if let Some(session_id) = cookies.get(SESSION_COOKIE_NAME) {
-        match verify_session_id(session_id, database).await {
-            Ok(true) => Ok(next.run(req).await),
-            Ok(false) => create_session(req, next).await,
-            Err(error) => {
-                tracing::error!(
-                    "Error while veryfing session_id. SessionId=[{}], Error=[{}]",
-                    session_id,
-                    error
-                );
-
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Unable to verify session id.".into(),
-                ))
-            }
-        }
-    } else {
-        create_session(req, next).await
+pub fn cookie_session_id(headers: &HeaderMap) -> Option<SessionId> {
+    headers
+        .typed_get::<HeaderCookie>()
+        .and_then(|cookies| cookies.get(SESSION_COOKIE_NAME).map(str::to_owned))
+}
+
+pub(super) fn created_session_id(extensions: &Extensions) -> Option<SessionId> {
+    extensions
+        .get::<CreatedSession>()
+        .and_then(|created| created.0.lock().unwrap().clone())
+}
+
+pub(super) fn session_created(
+    extensions: &Extensions,
+    session_id: &SessionId,
+) -> Result<(), AppError> {
+    let created = extensions.get::<CreatedSession>().ok_or_else(|| {
+        AppError::Internal("Session was created outside of ensure_session middleware.".into())
+    })?;
+
+    created.replace(session_id);
+
+    Ok(())
+}
+
+/// Lets handlers create sessions lazily, by extracting `SessionInfo`,
+/// and sends cookie of the created session back to the client.
+/// Requests which never need a session do not touch the database.
+pub async fn ensure_session<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, AppError> {
+    let config = req
+        .extensions()
+        .get::<Arc<Config>>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("Unable to get config from Request.".into()))?;
+
+    let created = CreatedSession::default();
+    req.extensions_mut().insert(created.clone());
+
+    let mut response = next.run(req).await;
+
+    if let Some(session_id) = created.0.lock().unwrap().take() {
+        let cookie = create_session_cookie(session_id, &config);
+        response.headers_mut().insert(
+            SET_COOKIE,
+            HeaderValue::from_str(&cookie.to_string()).unwrap(),
+        );
     }
+
+    Ok(response)
 }
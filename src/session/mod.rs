@@ -1,32 +1,58 @@
+mod cache;
+mod csrf;
 mod management;
 
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, net::SocketAddr, sync::Arc};
 
 use async_trait::async_trait;
-use axum::{
-    extract::{FromRequest, RequestParts},
-    headers::HeaderMapExt,
-    http::StatusCode,
-};
-use chrono::NaiveDateTime;
+use axum::extract::{ConnectInfo, FromRequest, RequestParts};
+use chrono::{Duration, NaiveDateTime};
 use rand::{thread_rng, Rng};
 use sqlx::{query, query_as, FromRow, PgPool, Row};
+use utoipa::OpenApi;
+
+pub use cache::{invalidation_task, listen_for_invalidations, notify_invalidation, SessionCache};
+pub(crate) use csrf::tokens_match;
+pub use csrf::{csrf_token, verify_csrf, CSRF_HEADER_NAME};
+pub use management::{cookie_session_id, ensure_session, CreatedSession, SESSION_COOKIE_NAME};
+
+use crate::{
+    auth::{AccountStatus, Suspension},
+    config::Config,
+    database::Database,
+    error::AppError,
+    logging,
+    metrics::Metrics,
+};
 
-pub use management::ensure_session;
-
-use crate::auth::Permissions;
+#[derive(OpenApi)]
+#[openapi(paths(csrf::csrf_token))]
+pub struct ApiDoc;
 
-#[derive(FromRow, Debug)]
+#[derive(Clone, FromRow)]
 pub struct SessionInfo {
     session_id: SessionId,
     expiration_date: NaiveDateTime,
     username: Option<String>,
+    csrf_token: String,
+}
+
+/// Never reveals the session id nor the CSRF token.
+impl std::fmt::Debug for SessionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionInfo")
+            .field("session", &logging::redact(&self.session_id))
+            .field("expiration_date", &self.expiration_date)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug)]
 pub enum SessionError {
     SessionIdNotFound,
     SessionExpired,
+    TooManySessions,
     DatabaseError(String),
 }
 
@@ -39,6 +65,12 @@ impl Display for SessionError {
             Self::SessionExpired => {
                 write!(f, "Session of given id has expired.")
             }
+            Self::TooManySessions => {
+                write!(
+                    f,
+                    "Too many anonymous sessions were created from this address."
+                )
+            }
             Self::DatabaseError(error) => {
                 write!(f, "DatabaseError. {error}")
             }
@@ -47,21 +79,48 @@ impl Display for SessionError {
 }
 
 const SESSION_ID_BITS: usize = 256; // Must be multiple of 8.
+const SESSION_ID_CONSTRAINT: &str = "session_info_session_id_key";
 pub type SessionId = String;
 pub type SessionIdReference<'a> = &'a str;
 
 impl SessionInfo {
-    async fn try_insert(&self, database: &PgPool) -> Result<(), sqlx::Error> {
+    /// Returns false if the limit of anonymous sessions
+    /// of the client address has been reached.
+    async fn try_insert(
+        &self,
+        database: &PgPool,
+        client_ip: Option<&str>,
+        max_anonymous: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = database.begin().await?;
+
+        // Serializes insertions from one address, so they cannot all pass the count at once.
+        if let (Some(client_ip), 1..) = (client_ip, max_anonymous) {
+            let lock_stmt = include_str!("../../postgres/session/lock_client_ip.sql");
+            sqlx::query(lock_stmt)
+                .bind(client_ip)
+                .execute(&mut transaction)
+                .await?;
+        }
+
         let query_stmt = include_str!("../../postgres/session/insert_session.sql");
         let query_prepared = sqlx::query(query_stmt)
             .bind(&self.session_id)
             .bind(self.expiration_date)
-            .bind(&self.username);
-
-        match query_prepared.execute(database).await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+            .bind(&self.username)
+            .bind(&self.csrf_token)
+            .bind(client_ip)
+            .bind(i64::from(max_anonymous))
+            .bind(chrono::Utc::now().naive_utc());
+
+        let inserted = query_prepared
+            .execute(&mut transaction)
+            .await?
+            .rows_affected()
+            == 1;
+        transaction.commit().await?;
+
+        Ok(inserted)
     }
 
     async fn try_read(
@@ -69,21 +128,71 @@ impl SessionInfo {
         database: &PgPool,
     ) -> Result<Option<Self>, sqlx::Error> {
         let query_stmt = include_str!("../../postgres/session/read_session.sql");
-        let query_prepared = query_as(query_stmt).bind(&session_id);
+        let query_prepared = query_as(query_stmt).bind(session_id);
 
         query_prepared.fetch_optional(database).await
     }
 
-    fn new(session_id: SessionId) -> SessionInfo {
-        let expiration_date = chrono::Utc::now().naive_utc() + *crate::SESSION_TIME;
+    /// Reads session from the replica. Sessions created moments ago may
+    /// not be replicated yet, so missing ones are looked up on the primary.
+    async fn read(
+        session_id: SessionIdReference<'_>,
+        database: &Database,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        match database
+            .read(|reader| Self::try_read(session_id, reader))
+            .await?
+        {
+            None if database.has_replica() => Self::try_read(session_id, database.writer()).await,
+            info => Ok(info),
+        }
+    }
+
+    /// Reads session through the cache, storing it there if it belongs to logged in user.
+    async fn read_cached(
+        session_id: SessionIdReference<'_>,
+        database: &Database,
+        cache: &SessionCache,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        if let Some(info) = cache.session(session_id) {
+            return Ok(Some(info));
+        }
+
+        // Replica may still return a session evicted moments ago, which
+        // would then be served for the whole TTL, so misses go to the primary.
+        let generation = cache.generation();
+        let info = if cache.enabled() {
+            Self::try_read(session_id, database.writer()).await?
+        } else {
+            Self::read(session_id, database).await?
+        };
+
+        if let Some(info) = &info {
+            cache.store_session(info, generation);
+        }
+
+        Ok(info)
+    }
+
+    fn new(session_id: SessionId, lifetime: Duration) -> SessionInfo {
+        let expiration_date = chrono::Utc::now().naive_utc() + lifetime;
 
         SessionInfo {
             session_id,
             expiration_date,
             username: None,
+            csrf_token: csrf::generate_csrf_token(),
         }
     }
 
+    pub fn session_id(&self) -> SessionIdReference<'_> {
+        &self.session_id
+    }
+
+    pub fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+
     pub fn username(&self) -> Option<&str> {
         match &self.username {
             None => None,
@@ -91,24 +200,50 @@ impl SessionInfo {
         }
     }
 
-    pub async fn read_permissions(
+    pub async fn read_account_status(
         &self,
-        database: &PgPool,
-    ) -> Result<Option<Permissions>, sqlx::Error> {
-        if self.username.is_none() {
-            return Ok(None);
+        database: &Database,
+        cache: &SessionCache,
+    ) -> Result<Option<AccountStatus>, sqlx::Error> {
+        let username = match &self.username {
+            Some(username) => username,
+            None => return Ok(None),
+        };
+
+        if let Some(status) = cache.account(username) {
+            return Ok(Some(status));
+        }
+
+        // Like sessions, cached status must not come from a lagging replica.
+        let generation = cache.generation();
+        let status = Self::read_uncached_status(username, database, cache.enabled()).await?;
+
+        if let Some(status) = &status {
+            cache.store_account(username, status, generation);
         }
 
+        Ok(status)
+    }
+
+    async fn read_uncached_status(
+        username: &str,
+        database: &Database,
+        from_primary: bool,
+    ) -> Result<Option<AccountStatus>, sqlx::Error> {
         let read_stmt = include_str!("../../postgres/session/read_permissions.sql");
+        let read = |reader| query(read_stmt).bind(username).fetch_optional(reader);
 
-        let query_prepared = query(read_stmt).bind(self.username.as_ref().unwrap());
-        let query_result = query_prepared.fetch_optional(database).await?;
+        let query_result = if from_primary {
+            read(database.writer()).await?
+        } else {
+            database.read(read).await?
+        };
 
         match query_result {
             None => {
                 tracing::error!(
-                    "Cannot find row with username = [{}] in order to read permissions.",
-                    self.username.as_ref().unwrap()
+                    "Cannot find row of user [{}] in order to read permissions.",
+                    logging::redact(username)
                 );
 
                 Err(sqlx::Error::Protocol(
@@ -116,7 +251,17 @@ impl SessionInfo {
                 ))
             }
             Some(row) => match row.try_get::<Option<String>, _>("permissions") {
-                Ok(Some(permissions)) => Ok(Some(permissions.parse().unwrap())),
+                Ok(Some(permissions)) => Ok(Some(AccountStatus {
+                    permissions: permissions
+                        .parse()
+                        .map_err(|e: &str| sqlx::Error::Decode(e.into()))?,
+                    disabled: row.try_get("disabled")?,
+                    password_reset_required: row.try_get("password_reset_required")?,
+                    suspension: Suspension::active(
+                        row.try_get("suspended_until")?,
+                        row.try_get("suspension_reason")?,
+                    ),
+                })),
                 Ok(None) => Ok(None),
                 Err(error) => {
                     tracing::error!(
@@ -129,6 +274,37 @@ impl SessionInfo {
             },
         }
     }
+
+    /// Reads session of given id, unless it does not exist or has expired.
+    async fn current(
+        session_id: Option<SessionId>,
+        database: &Database,
+        cache: &SessionCache,
+    ) -> Result<Option<SessionInfo>, AppError> {
+        let session_id = match session_id {
+            Some(session_id) => session_id,
+            None => return Ok(None),
+        };
+
+        match SessionInfo::read_cached(&session_id, database, cache).await? {
+            Some(info) if info.expiration_date > chrono::Utc::now().naive_utc() => {
+                let span = tracing::Span::current();
+                span.record("session", logging::redact(&session_id).as_str());
+
+                if let Some(username) = &info.username {
+                    span.record("user", logging::redact(username).as_str());
+                }
+
+                Ok(Some(info))
+            }
+            Some(_) => {
+                cache.evict_session(&session_id);
+                remove_session(&session_id, database.writer()).await?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 fn generate_session_id() -> String {
@@ -138,16 +314,23 @@ fn generate_session_id() -> String {
     base64::encode(array)
 }
 
-pub async fn fresh_session(database: &PgPool) -> Result<SessionId, String> {
+pub async fn fresh_session(
+    database: &PgPool,
+    lifetime: Duration,
+    client_ip: Option<&str>,
+    max_anonymous: u32,
+) -> Result<SessionInfo, SessionError> {
     loop {
-        let session_id = generate_session_id();
-
-        let fresh_info = SessionInfo::new(session_id.clone());
-
-        match fresh_info.try_insert(database).await {
-            Ok(()) => return Ok(session_id),
-            Err(sqlx::Error::Database(e)) if e.constraint() == Some("session_id") => {}
-            Err(e) => return Err(e.to_string()),
+        let fresh_info = SessionInfo::new(generate_session_id(), lifetime);
+
+        match fresh_info
+            .try_insert(database, client_ip, max_anonymous)
+            .await
+        {
+            Ok(true) => return Ok(fresh_info),
+            Ok(false) => return Err(SessionError::TooManySessions),
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some(SESSION_ID_CONSTRAINT) => {}
+            Err(e) => return Err(SessionError::DatabaseError(e.to_string())),
         }
     }
 }
@@ -165,32 +348,45 @@ pub async fn check_session(
     }
 }
 
-pub async fn update_session(
-    session_id: SessionId,
+/// Moves session of the request to a new id and CSRF token once the user
+/// logs in, so that identifiers known before, possibly planted by an
+/// attacker, cannot be used to act on behalf of the user.
+pub async fn renew_session(
+    session_info: &SessionInfo,
+    created: &CreatedSession,
     database: &PgPool,
+    cache: &SessionCache,
     username: &str,
-) -> Result<(), SessionError> {
-    let update_stmt = include_str!("../../postgres/session/update_session.sql");
-    let expiration_date = chrono::Utc::now().naive_utc() + *crate::SESSION_TIME;
+    lifetime: Duration,
+) -> Result<SessionInfo, SessionError> {
+    let renew_stmt = include_str!("../../postgres/session/renew_session.sql");
 
-    let query_prepared = query(update_stmt)
-        .bind(username)
-        .bind(&expiration_date)
-        .bind(&session_id);
+    loop {
+        let mut renewed = SessionInfo::new(generate_session_id(), lifetime);
+        renewed.username = Some(username.to_owned());
 
-    match query_prepared.execute(database).await {
-        Err(e) => Err(SessionError::DatabaseError(e.to_string())),
-        Ok(result) => {
-            if result.rows_affected() == 1 {
-                Ok(())
-            } else {
-                Err(SessionError::SessionIdNotFound)
+        let query_prepared = query(renew_stmt)
+            .bind(&renewed.session_id)
+            .bind(&renewed.csrf_token)
+            .bind(username)
+            .bind(renewed.expiration_date)
+            .bind(&session_info.session_id);
+
+        match query_prepared.execute(database).await {
+            Ok(result) if result.rows_affected() == 1 => {
+                cache.evict_session(&session_info.session_id);
+                created.replace(&renewed.session_id);
+
+                return Ok(renewed);
             }
+            Ok(_) => return Err(SessionError::SessionIdNotFound),
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some(SESSION_ID_CONSTRAINT) => {}
+            Err(e) => return Err(SessionError::DatabaseError(e.to_string())),
         }
     }
 }
 
-async fn remove_session(
+pub async fn remove_session(
     session_id: SessionIdReference<'_>,
     database: &PgPool,
 ) -> Result<(), SessionError> {
@@ -203,7 +399,7 @@ async fn remove_session(
         Err(e) => {
             tracing::error!(
                 "Error occured while removing session [{}] from database. Error = [{}]",
-                session_id,
+                logging::redact(session_id),
                 e
             );
 
@@ -212,102 +408,234 @@ async fn remove_session(
     }
 }
 
-pub async fn verify_session_id(
-    session_id: SessionIdReference<'_>,
-    database: &PgPool,
-) -> Result<bool, SessionError> {
-    match SessionInfo::try_read(session_id, database).await {
-        Ok(result) => {
-            if let Some(info) = result {
-                let current_date = chrono::Utc::now().naive_utc();
-
-                if info.expiration_date <= current_date {
-                    remove_session(session_id, database).await;
-                    Ok(false)
-                } else {
-                    Ok(true)
-                }
-            } else {
-                Ok(false)
-            }
-        }
-        Err(error) => {
-            tracing::error!(
-                "Error occured while veryfing session_id = [{}]. Error = [{}].",
-                session_id,
-                error
-            );
-            Err(SessionError::DatabaseError(error.to_string()))
-        }
+/// Removes every session of given user, effectively logging them
+/// out on all devices. Returns number of terminated sessions.
+pub async fn remove_user_sessions<'e, E>(username: &str, executor: E) -> Result<u64, sqlx::Error>
+where
+    E: sqlx::postgres::PgExecutor<'e>,
+{
+    let remove_stmt = include_str!("../../postgres/session/remove_user_sessions.sql");
+
+    let result = query(remove_stmt).bind(username).execute(executor).await?;
+
+    Ok(result.rows_affected())
+}
+
+fn extension<T, B>(req: &RequestParts<B>) -> Result<T, AppError>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    req.extensions().get::<T>().cloned().ok_or_else(|| {
+        AppError::Internal(format!(
+            "Unable to get {} from extensions in session extractor.",
+            std::any::type_name::<T>()
+        ))
+    })
+}
+
+/// Returns given id if it belongs to an unexpired session. Unlike the
+/// extractors, it neither creates sessions nor removes expired ones.
+pub async fn existing_session_id(
+    session_id: SessionId,
+    database: &Database,
+    cache: &SessionCache,
+) -> Result<Option<SessionId>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+
+    Ok(SessionInfo::read_cached(&session_id, database, cache)
+        .await?
+        .filter(|info| info.expiration_date > now)
+        .map(|info| info.session_id))
+}
+
+/// Marks routes which must never create a session, for example
+/// ones used by API clients. Layer it as an `Extension` on them.
+#[derive(Clone, Copy)]
+pub struct Stateless;
+
+/// Session of the request, if there is a valid one. Unlike
+/// extracting `SessionInfo`, it never creates a new session.
+pub struct ExistingSession(pub Option<SessionInfo>);
+
+#[async_trait]
+impl<B> FromRequest<B> for ExistingSession
+where
+    B: Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let database = extension::<Arc<Database>, _>(req)?;
+        let cache = extension::<Arc<SessionCache>, _>(req)?;
+
+        Ok(ExistingSession(
+            SessionInfo::current(request_session_id(req), &database, &cache).await?,
+        ))
     }
 }
 
+/// Id of the session created earlier while handling
+/// the request, or the one given in the cookie.
+fn request_session_id<B>(req: &RequestParts<B>) -> Option<SessionId> {
+    management::created_session_id(req.extensions())
+        .or_else(|| management::cookie_session_id(req.headers()))
+}
+
+/// Session of the request. It is created and stored only when
+/// there is no valid one yet, so that requests which never extract
+/// it, like ones of crawlers and probes, do not leave sessions behind.
 #[async_trait]
 impl<B> FromRequest<B> for SessionInfo
 where
     B: Send,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = AppError;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let cookies = match req.headers().typed_get::<axum::headers::Cookie>() {
-            Some(cookies) => cookies,
-            None => {
-                tracing::warn!(
-                    "No cookies was found - possible problem with ensure_session middleware."
-                );
+        let database = extension::<Arc<Database>, _>(req)?;
+        let cache = extension::<Arc<SessionCache>, _>(req)?;
 
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "No cookies were found".into(),
-                ));
-            }
-        };
+        if let Some(info) = SessionInfo::current(request_session_id(req), &database, &cache).await?
+        {
+            return Ok(info);
+        }
 
-        let database = match req.extensions().get::<Arc<PgPool>>() {
-            Some(db) => db,
-            None => {
-                tracing::error!("Unable to get database from extensions in SessionInfo extractor.");
+        if req.extensions().get::<Stateless>().is_some() {
+            return Err(AppError::MissingSession);
+        }
 
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Cannot establish connection with database.".into(),
-                ));
-            }
-        };
+        let config = extension::<Arc<Config>, _>(req)?;
+        let metrics = extension::<Arc<Metrics>, _>(req)?;
+        let client_ip = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+
+        let info = fresh_session(
+            database.writer(),
+            config.session.lifetime(),
+            client_ip.as_deref(),
+            config.session.max_anonymous_per_ip,
+        )
+        .await?;
+
+        management::session_created(req.extensions(), &info.session_id)?;
+        metrics.sessions_created.inc();
+        tracing::Span::current().record("session", logging::redact(&info.session_id).as_str());
+
+        Ok(info)
+    }
+}
 
-        let session_id = match cookies.get(management::SESSION_COOKIE_NAME) {
-            Some(value) => value,
-            None => {
-                tracing::error!("Unable to get session cookie. Possible problem with ensure_session middleware.");
+#[cfg(test)]
+mod tests {
+    use axum::http::header::AUTHORIZATION;
+    use serde_json::json;
+    use sqlx::Row;
 
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    "No session cookie was found".into(),
-                ));
-            }
-        };
+    use crate::{config::Config, testing::TestApp};
 
-        match SessionInfo::try_read(session_id, database).await {
-            Ok(Some(info)) => Ok(info),
-            Ok(None) => {
-                tracing::warn!(
-                    "Session cookie cannot be found in database. SessionId = [{}]",
-                    session_id
-                );
+    async fn count_sessions(app: &TestApp) -> i64 {
+        sqlx::query("SELECT COUNT(*) AS sessions FROM credentials.session_info")
+            .fetch_one(app.database())
+            .await
+            .and_then(|row| row.try_get("sessions"))
+            .unwrap()
+    }
 
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Cannot find user session in database. Try again.".into(),
-                ))
-            }
-            Err(error) => {
-                tracing::error!("Database error has occured in SessionInfo extractor. Session_id = [{}]. Error = [{}]", session_id, error);
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Database error has occured".into(),
-                ))
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn sessions_are_created_lazily() {
+        let app = TestApp::spawn().await;
+        let mut client = app.client();
+
+        assert_eq!(client.get("/health/live").await.status, 200);
+        assert!(client.session_id().is_none());
+        assert_eq!(count_sessions(&app).await, 0);
+
+        let token = client.fetch_csrf_token().await;
+        let session_id = client.session_id().unwrap().to_owned();
+        assert_eq!(client.fetch_csrf_token().await, token);
+        assert_eq!(client.session_id(), Some(session_id.as_str()));
+        assert_eq!(count_sessions(&app).await, 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn unsafe_requests_require_csrf_token() {
+        let app = TestApp::spawn().await;
+        let credentials = json!({ "username": "alice", "password": "correct horse" });
+
+        let mut client = app.client();
+        let missing = client.post("/auth/signup", credentials.clone()).await;
+        assert_eq!(missing.status, 403);
+        assert_eq!(missing.code(), "InvalidCsrfToken");
+
+        // There is no token authentication, so such requests still rely on the cookie.
+        assert_eq!(client.get("/auth/csrf").await.status, 200);
+        client.set_header(AUTHORIZATION, "Bearer forged");
+        assert_eq!(
+            client
+                .post("/auth/signup", credentials.clone())
+                .await
+                .code(),
+            "InvalidCsrfToken"
+        );
+
+        // Token of another session is not accepted either.
+        client.fetch_csrf_token().await;
+        let foreign_token = app.client().fetch_csrf_token().await;
+        client.set_csrf_token(foreign_token);
+        assert_eq!(
+            client
+                .post("/auth/signup", credentials.clone())
+                .await
+                .code(),
+            "InvalidCsrfToken"
+        );
+
+        client.fetch_csrf_token().await;
+        assert_eq!(client.post("/auth/signup", credentials).await.status, 201);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn anonymous_sessions_are_limited_per_address() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.session.max_anonymous_per_ip = 2;
+        })
+        .await;
+
+        app.client().fetch_csrf_token().await;
+        app.client().fetch_csrf_token().await;
+
+        let response = app.client().get("/auth/csrf").await;
+        assert_eq!(response.status, 429);
+        assert_eq!(response.code(), "TooManySessions");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires PostgreSQL configured through BG_* env variables"]
+    async fn concurrent_anonymous_sessions_respect_limit() {
+        let app = TestApp::with_config(|config: &mut Config| {
+            config.session.max_anonymous_per_ip = 3;
+        })
+        .await;
+
+        let requests: Vec<_> = (0..20)
+            .map(|_| {
+                let mut client = app.client();
+                tokio::spawn(async move { client.get("/auth/csrf").await.status })
+            })
+            .collect();
+
+        let mut created = 0;
+        for request in requests {
+            if request.await.unwrap() == 200 {
+                created += 1;
             }
         }
+
+        assert_eq!(created, 3);
     }
 }
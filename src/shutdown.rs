@@ -0,0 +1,41 @@
+/// Every request was served and resources were released.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Server failed or stopped without being asked to.
+pub const EXIT_SERVER_ERROR: i32 = 1;
+/// Some requests were still running when the shutdown timeout expired.
+pub const EXIT_DRAIN_TIMEOUT: i32 = 3;
+
+/// How long closing database connections may add to the shutdown
+/// timeout, which has already been spent waiting for requests.
+pub const POOL_CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Completes once the process is asked to stop with SIGTERM or SIGINT.
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            tracing::error!("Unable to listen for SIGINT. Error = [{}]", error);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(error) => {
+                tracing::error!("Unable to listen for SIGTERM. Error = [{}]", error);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => tracing::info!("Received SIGINT."),
+        _ = terminate => tracing::info!("Received SIGTERM."),
+    }
+}
@@ -0,0 +1,420 @@
+//! End-to-end test harness. Every `TestApp` serves the full application
+//! from `main` against its own freshly migrated database, created through
+//! the connection configured by `BG_*` env variables. Its user therefore
+//! needs the `CREATEDB` privilege.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::ConnectInfo,
+    http::{
+        header::{self, CONTENT_TYPE, COOKIE, SET_COOKIE},
+        HeaderMap, HeaderValue, Method, Request, StatusCode,
+    },
+    Router,
+};
+use cookie::Cookie;
+use serde_json::{json, Value};
+use sqlx::{postgres::PgPool, Executor};
+use tokio::sync::watch;
+use tower::ServiceExt;
+
+use crate::{
+    auth::{Hasher, Permissions},
+    config::{Config, DatabaseConfig, HashingConfig},
+    database::{connect, initialize_database, Database},
+    metrics::Metrics,
+    migrations,
+    rate_limit::RateLimiter,
+    session::{self, SessionCache, CSRF_HEADER_NAME},
+};
+
+const PEPPER: &[u8] = b"integration tests pepper";
+const CLIENT_ADDRESS: ([u8; 4], u16) = ([127, 0, 0, 1], 40000);
+
+/// Application running against a throwaway database, which is dropped with it.
+pub struct TestApp {
+    router: Router,
+    database: Arc<Database>,
+    session_cache: Arc<SessionCache>,
+    config: Arc<Config>,
+    name: String,
+    admin_database: DatabaseConfig,
+    replica: Option<PgPool>,
+    /// Stops listening for session invalidations once dropped.
+    _shutdown: watch::Sender<()>,
+}
+
+impl TestApp {
+    pub async fn spawn() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Lets the test adjust config before the application is built.
+    /// Rate limits are disabled and hashing is cheap by default.
+    pub async fn with_config(adjust: impl FnOnce(&mut Config)) -> Self {
+        Self::build(adjust, false).await
+    }
+
+    /// Application reading through a replica of its database, which stops
+    /// seeing changes of the primary once [`TestApp::freeze_replica`] is called.
+    pub async fn with_lagging_replica() -> Self {
+        Self::build(|_| {}, true).await
+    }
+
+    async fn build(adjust: impl FnOnce(&mut Config), lagging_replica: bool) -> Self {
+        let mut config = Config::load(None).expect("BG_* env variables should configure tests.");
+        config.rate_limit.enabled = false;
+        config.hashing.memory_blocks = 64;
+        config.hashing.iterations = 1;
+        adjust(&mut config);
+
+        let admin_database = config.database.clone();
+        let name = format!("budgeters_test_{:016x}", rand::random::<u64>());
+
+        connect(&admin_database)
+            .await
+            .expect("BG_* env variables should configure tests.")
+            .execute(format!("CREATE DATABASE {name}").as_str())
+            .await
+            .expect("Unable to create test database, does the user have CREATEDB privilege?");
+
+        config.database.name = name.clone();
+        let primary = initialize_database(&config.database).await.unwrap();
+        migrations::migrate(primary.writer(), false).await.unwrap();
+
+        // Single connection, so that the one frozen in a transaction serves every read.
+        let replica = match lagging_replica {
+            true => Some(
+                connect(&DatabaseConfig {
+                    max_connections: 1,
+                    min_connections: 0,
+                    ..config.database.clone()
+                })
+                .await
+                .unwrap(),
+            ),
+            false => None,
+        };
+        let database = match &replica {
+            Some(replica) => Arc::new(Database::new(
+                primary.writer().clone(),
+                Some(replica.clone()),
+            )),
+            None => Arc::new(primary),
+        };
+
+        let config = Arc::new(config);
+        let metrics = Arc::new(Metrics::new());
+        let hasher = Hasher::new(
+            PEPPER,
+            &config.hashing,
+            metrics.password_hashing_duration.clone(),
+        );
+        let rate_limiter = Arc::new(RateLimiter::new(&config.rate_limit, database.clone()));
+
+        let session_cache = Arc::new(SessionCache::new(&config.session));
+        let (shutdown, shutdown_receiver) = watch::channel(());
+        if config.session.cache_listen {
+            let listener = session::listen_for_invalidations(&database).await.unwrap();
+            tokio::spawn(session::invalidation_task(
+                session_cache.clone(),
+                listener,
+                shutdown_receiver,
+            ));
+        }
+
+        let router = crate::application(
+            config.clone(),
+            database.clone(),
+            hasher,
+            session_cache.clone(),
+            metrics,
+            rate_limiter,
+        );
+
+        TestApp {
+            router,
+            database,
+            session_cache,
+            config,
+            name,
+            admin_database,
+            replica,
+            _shutdown: shutdown,
+        }
+    }
+
+    pub fn database(&self) -> &PgPool {
+        self.database.writer()
+    }
+
+    /// Makes the replica keep returning what the database contains now.
+    pub async fn freeze_replica(&self) {
+        let replica = self.replica.as_ref().expect("TestApp has no replica.");
+        let mut connection = replica.acquire().await.unwrap();
+
+        // Snapshot of the transaction left open outlives the connection's return to the pool.
+        connection
+            .execute("BEGIN ISOLATION LEVEL REPEATABLE READ; SELECT 1;")
+            .await
+            .unwrap();
+    }
+
+    pub fn session_cache(&self) -> &SessionCache {
+        &self.session_cache
+    }
+
+    /// Hasher producing the same hashes as the application's one.
+    pub fn hasher(&self) -> Hasher {
+        self.hasher_with(|_| {})
+    }
+
+    /// Hasher sharing the application's pepper, but not necessarily its parameters.
+    pub fn hasher_with(&self, adjust: impl FnOnce(&mut HashingConfig)) -> Hasher {
+        let mut hashing = self.config.hashing.clone();
+        adjust(&mut hashing);
+
+        Hasher::new(PEPPER, &hashing, Metrics::new().password_hashing_duration)
+    }
+
+    /// Client without any session, like a newly opened browser.
+    pub fn client(&self) -> TestClient {
+        TestClient {
+            router: self.router.clone(),
+            cookies: HashMap::new(),
+            csrf_token: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Signs up new user and returns client logged in as them.
+    pub async fn logged_in(&self, username: &str, password: &str) -> TestClient {
+        let mut client = self.client();
+
+        assert_eq!(client.signup(username, password).await.status, 201);
+        assert_eq!(client.login(username, password).await.status, 200);
+
+        client
+    }
+
+    pub async fn set_permissions(&self, username: &str, permissions: Permissions) {
+        let update_stmt = include_str!("../postgres/admin/update_permissions.sql");
+
+        sqlx::query(update_stmt)
+            .bind(username)
+            .bind(permissions.to_string())
+            .execute(self.database())
+            .await
+            .unwrap();
+
+        self.session_cache
+            .invalidate_user(username, self.database())
+            .await
+            .unwrap();
+    }
+}
+
+impl Drop for TestApp {
+    /// Database is dropped from separate runtime, since the
+    /// test one may already be shutting down.
+    fn drop(&mut self) {
+        let (admin_database, name) = (self.admin_database.clone(), self.name.clone());
+
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    connect(&admin_database)
+                        .await?
+                        .execute(format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)").as_str())
+                        .await
+                })
+        })
+        .join();
+
+        if !matches!(dropped, Ok(Ok(_))) {
+            eprintln!("Unable to drop test database.");
+        }
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// `Value::Null` when the body is empty, string when it is not JSON.
+    pub body: Value,
+}
+
+impl TestResponse {
+    /// `code` member of problem details.
+    pub fn code(&self) -> &str {
+        self.body["code"].as_str().unwrap_or_default()
+    }
+}
+
+/// Keeps cookies between requests and sends CSRF token of its
+/// session with unsafe requests, once the token has been fetched.
+pub struct TestClient {
+    router: Router,
+    cookies: HashMap<String, String>,
+    csrf_token: Option<String>,
+    headers: HeaderMap,
+}
+
+impl TestClient {
+    pub async fn get(&mut self, path: &str) -> TestResponse {
+        self.send(Method::GET, path, None).await
+    }
+
+    pub async fn post(&mut self, path: &str, body: Value) -> TestResponse {
+        self.send(Method::POST, path, Some(body)).await
+    }
+
+    pub async fn put(&mut self, path: &str, body: Value) -> TestResponse {
+        self.send(Method::PUT, path, Some(body)).await
+    }
+
+    pub async fn delete(&mut self, path: &str, body: Value) -> TestResponse {
+        self.send(Method::DELETE, path, Some(body)).await
+    }
+
+    pub async fn options(&mut self, path: &str) -> TestResponse {
+        self.send(Method::OPTIONS, path, None).await
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.cookies
+            .get(crate::session::SESSION_COOKIE_NAME)
+            .map(String::as_str)
+    }
+
+    /// Fetches CSRF token of the session, starting one if needed.
+    pub async fn fetch_csrf_token(&mut self) -> String {
+        let response = self.get("/auth/csrf").await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let token = response.body["csrf_token"].as_str().unwrap().to_owned();
+        self.csrf_token = Some(token.clone());
+        token
+    }
+
+    pub fn set_csrf_token(&mut self, token: String) {
+        self.csrf_token = Some(token);
+    }
+
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Sends given header with every following request.
+    pub fn set_header(&mut self, name: header::HeaderName, value: &str) {
+        self.headers
+            .insert(name, HeaderValue::from_str(value).unwrap());
+    }
+
+    pub async fn signup(&mut self, username: &str, password: &str) -> TestResponse {
+        self.ensure_csrf_token().await;
+        self.post(
+            "/auth/signup",
+            json!({ "username": username, "password": password }),
+        )
+        .await
+    }
+
+    /// Logging in replaces the session, so its new CSRF token is kept.
+    pub async fn login(&mut self, username: &str, password: &str) -> TestResponse {
+        self.ensure_csrf_token().await;
+        let response = self
+            .post(
+                "/auth/login",
+                json!({ "username": username, "password": password }),
+            )
+            .await;
+
+        if let Some(token) = response.body["csrf_token"].as_str() {
+            self.csrf_token = Some(token.to_owned());
+        }
+
+        response
+    }
+
+    pub async fn logout(&mut self) -> TestResponse {
+        self.ensure_csrf_token().await;
+        self.post("/auth/logout", json!({})).await
+    }
+
+    async fn ensure_csrf_token(&mut self) {
+        if self.csrf_token.is_none() {
+            self.fetch_csrf_token().await;
+        }
+    }
+
+    async fn send(&mut self, method: Method, path: &str, body: Option<Value>) -> TestResponse {
+        let mut request = Request::builder().method(method.clone()).uri(path);
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            request = request.header(COOKIE, cookies);
+        }
+
+        if let (false, Some(token)) = (method.is_safe(), &self.csrf_token) {
+            request = request.header(CSRF_HEADER_NAME, token);
+        }
+
+        let body = match body {
+            Some(body) => {
+                request = request.header(CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+
+        let mut request = request.body(body).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(CLIENT_ADDRESS)));
+
+        let mut response = self.router.clone().oneshot(request).await.unwrap();
+
+        for value in response.headers().get_all(SET_COOKIE) {
+            let cookie = Cookie::parse(value.to_str().unwrap().to_owned()).unwrap();
+
+            if cookie.value().is_empty() {
+                self.cookies.remove(cookie.name());
+            } else {
+                self.cookies
+                    .insert(cookie.name().to_owned(), cookie.value().to_owned());
+            }
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.body_mut().data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+
+        let body = match serde_json::from_slice(&bytes) {
+            Ok(body) => body,
+            Err(_) if bytes.is_empty() => Value::Null,
+            Err(_) => Value::String(String::from_utf8_lossy(&bytes).into_owned()),
+        };
+
+        TestResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use axum::{
+    extract::Host,
+    http::{StatusCode, Uri},
+    response::Redirect,
+    Extension, Router, Server,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::sync::watch;
+
+use crate::config::{Config, TlsConfig};
+
+/// Modification times of the certificate and key files.
+type FileVersions = Option<(SystemTime, SystemTime)>;
+
+fn file_versions(config: &TlsConfig) -> FileVersions {
+    let modified = |path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (
+        modified(&config.certificate_path),
+        modified(&config.key_path),
+    ) {
+        (Ok(certificate), Ok(key)) => Some((certificate, key)),
+        _ => None,
+    }
+}
+
+/// Reads certificate chain and key. Rustls accepts an empty chain,
+/// so files without any certificate are rejected here.
+async fn read_pem(config: &TlsConfig) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let certificate = tokio::fs::read(&config.certificate_path).await?;
+    let key = tokio::fs::read(&config.key_path).await?;
+
+    if rustls_pemfile::certs(&mut certificate.as_slice())?.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "No certificate found in PEM file.",
+        ));
+    }
+
+    Ok((certificate, key))
+}
+
+pub async fn load(config: &TlsConfig) -> std::io::Result<RustlsConfig> {
+    let (certificate, key) = read_pem(config).await?;
+    RustlsConfig::from_pem(certificate, key).await
+}
+
+/// Reloads certificate if its files have changed since `loaded`. When they
+/// cannot be used, the previous certificate is kept until they change again.
+async fn reload_if_changed(rustls: &RustlsConfig, config: &TlsConfig, loaded: &mut FileVersions) {
+    let current = file_versions(config);
+
+    if current.is_none() || current == *loaded {
+        return;
+    }
+
+    let reloaded = match read_pem(config).await {
+        Ok((certificate, key)) => rustls.reload_from_pem(certificate, key).await,
+        Err(error) => Err(error),
+    };
+
+    *loaded = current;
+
+    match reloaded {
+        Ok(()) => tracing::info!("Reloaded TLS certificate."),
+        Err(error) => {
+            tracing::warn!(
+                "Unable to reload TLS certificate, previous one stays in use. Error = [{}]",
+                error
+            )
+        }
+    }
+}
+
+/// Background task picking up renewed certificates, until shutdown is signalled.
+pub async fn reload_task(
+    rustls: RustlsConfig,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let mut loaded = file_versions(&config.tls);
+    let mut interval = tokio::time::interval(config.tls.reload_interval());
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        reload_if_changed(&rustls, &config.tls, &mut loaded).await;
+    }
+}
+
+/// Same host and path on the HTTPS port. Port is left
+/// out when it is the default one.
+fn https_location(host: &str, uri: &Uri, https_port: u16) -> String {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+
+    match https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    }
+}
+
+async fn redirect_to_https(
+    Host(host): Host,
+    uri: Uri,
+    Extension(https_port): Extension<u16>,
+) -> Result<Redirect, StatusCode> {
+    if host.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(Redirect::permanent(&https_location(
+        &host, &uri, https_port,
+    )))
+}
+
+/// Redirects plain HTTP requests to HTTPS until shutdown is signalled.
+pub async fn serve_redirect(
+    address: SocketAddr,
+    https_port: u16,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let router = Router::new()
+        .fallback(axum::routing::any(redirect_to_https))
+        .layer(Extension(https_port));
+
+    let outcome = Server::bind(&address)
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.changed().await;
+        })
+        .await;
+
+    if let Err(error) = outcome {
+        tracing::error!("HTTPS redirect server failed. Error = [{}]", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use axum::{http::Uri, routing::get, Router};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::{
+        rustls::{self, ServerName},
+        TlsConnector,
+    };
+
+    use super::*;
+
+    /// Writes new self-signed certificate for `localhost`
+    /// and returns it, so that clients can trust it.
+    fn write_certificate(config: &TlsConfig, modified: SystemTime) -> rustls::Certificate {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+
+        for (path, content) in [
+            (&config.certificate_path, generated.serialize_pem().unwrap()),
+            (&config.key_path, generated.serialize_private_key_pem()),
+        ] {
+            std::fs::write(path, content).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        rustls::Certificate(generated.serialize_der().unwrap())
+    }
+
+    /// Sends request over TLS, trusting only given certificate.
+    async fn request(
+        address: SocketAddr,
+        trusted: &rustls::Certificate,
+    ) -> std::io::Result<String> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(trusted).unwrap();
+        let client = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let stream = tokio::net::TcpStream::connect(address).await?;
+        let mut stream = TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+
+        Ok(response)
+    }
+
+    fn temporary_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("budgeters-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tls_config(dir: &Path) -> TlsConfig {
+        TlsConfig {
+            enabled: true,
+            certificate_path: dir.join("certificate.pem"),
+            key_path: dir.join("key.pem"),
+            ..TlsConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn renewed_certificate_is_served() {
+        let dir = temporary_dir();
+        let config = tls_config(&dir);
+        let issued = SystemTime::now() - Duration::from_secs(60);
+
+        let first = write_certificate(&config, issued);
+        let rustls = load(&config).await.unwrap();
+        let mut loaded = file_versions(&config);
+
+        let handle = axum_server::Handle::new();
+        let router = Router::new().route("/", get(|| async { "served" }));
+        tokio::spawn(
+            axum_server::bind_rustls("127.0.0.1:0".parse().unwrap(), rustls.clone())
+                .handle(handle.clone())
+                .serve(router.into_make_service()),
+        );
+        let address = handle.listening().await.unwrap();
+
+        let response = request(address, &first).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("served"));
+
+        let second = write_certificate(&config, SystemTime::now());
+        reload_if_changed(&rustls, &config, &mut loaded).await;
+
+        assert!(request(address, &first).await.is_err());
+        assert!(request(address, &second).await.unwrap().ends_with("served"));
+
+        // Partially written renewal keeps the previous certificate in use.
+        std::fs::write(&config.certificate_path, "-----BEGIN").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&config.certificate_path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        reload_if_changed(&rustls, &config, &mut loaded).await;
+
+        assert!(request(address, &second).await.unwrap().ends_with("served"));
+
+        handle.shutdown();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn redirect_keeps_host_and_path() {
+        let uri: Uri = "/auth/csrf?x=1".parse().unwrap();
+
+        assert_eq!(
+            https_location("example.com:80", &uri, 443),
+            "https://example.com/auth/csrf?x=1"
+        );
+        assert_eq!(
+            https_location("[::1]", &uri, 8443),
+            "https://[::1]:8443/auth/csrf?x=1"
+        );
+        assert_eq!(
+            https_location("[::1]:8080", &Uri::from_static("/"), 8443),
+            "https://[::1]:8443/"
+        );
+    }
+}
@@ -6,5 +6,9 @@ export BG_PORT="5432"
 export BG_USER="budgetersapp"
 export BG_PASSWORD="1234"
 export BG_DATABASE="budgetersdb"
+# Development only, same pepper as the one previously compiled in.
+export BG_PEPPER="AQID"
+export BG_REGISTRATION_MODE="open"
+export BG_DELETION_GRACE_HOURS="168"
 
 cargo run